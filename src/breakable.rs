@@ -0,0 +1,172 @@
+use crate::index::SpatialIndex;
+use crate::loader::TileFlags;
+use crate::physics::{feet_rect, is_heavy, Actor, IntRect, TileBody};
+use crate::resources::SceneResources;
+use crate::vfx::SmokeParticle;
+use crate::visibility::update_obscurers;
+use hecs::{CommandBuffer, Entity, World};
+use std::collections::HashMap;
+
+const CRUMBLE_FRAMES: u8 = 8;
+const RESPAWN_FRAMES: u16 = 150;
+
+struct BrokenTile {
+    flags: TileFlags,
+    respawn: Option<u16>,
+}
+
+// attached to any tile body containing destructible or crumbling tiles
+pub struct Breakable {
+    crumbling: HashMap<usize, u8>,
+    broken: HashMap<usize, BrokenTile>,
+}
+
+impl Breakable {
+    pub fn new() -> Self {
+        Self {
+            crumbling: HashMap::new(),
+            broken: HashMap::new(),
+        }
+    }
+
+    pub fn shake_offset(&self, index: usize) -> f32 {
+        match self.crumbling.get(&index) {
+            Some(n) => (n % 2) as f32 * 2.0 - 1.0,
+            None => 0.0,
+        }
+    }
+
+    fn break_tile(
+        &mut self,
+        body: &mut TileBody,
+        index: usize,
+        respawn: bool,
+        buffer: &mut CommandBuffer,
+    ) {
+        self.broken.insert(
+            index,
+            BrokenTile {
+                flags: body.data[index],
+                respawn: if respawn { Some(RESPAWN_FRAMES) } else { None },
+            },
+        );
+        body.data[index] = TileFlags::empty();
+        let (x, y) = body.tile_rect(index).centre_int();
+        let mut a = quad_rand::gen_range(0.0, std::f32::consts::TAU);
+        for _ in 0..3 {
+            buffer.spawn((SmokeParticle::new_from_centre(x, y, a, 8.0),));
+            a += std::f32::consts::TAU / std::f32::consts::E;
+        }
+    }
+
+    pub fn update(resources: &mut SceneResources, buffer: &mut CommandBuffer) {
+        let world = resources.world_ref.lock().unwrap();
+        let mut changed = Vec::new();
+        for (id, (body, br)) in world.query::<(&mut TileBody, &mut Breakable)>().iter() {
+            let body_rect = body.get_rect();
+            // start the countdown on any crumbling tiles that are being stood on
+            for (e, (actor, rect)) in world.query::<(&Actor, &IntRect)>().iter() {
+                let feet = feet_rect(rect);
+                if actor.grounded && feet.intersects(&body_rect) && is_heavy(&world, e) {
+                    for index in body.tiles_in_rect(&feet) {
+                        if body.data[index].is_crumbling() {
+                            br.crumbling.entry(index).or_insert(CRUMBLE_FRAMES);
+                        }
+                    }
+                }
+            }
+
+            let mut collapsed = Vec::new();
+            for (index, n) in br.crumbling.iter_mut() {
+                *n -= 1;
+                if *n == 0 {
+                    collapsed.push(*index);
+                }
+            }
+            let mut restored = Vec::new();
+            for (index, tile) in br.broken.iter_mut() {
+                match &mut tile.respawn {
+                    Some(0) => {
+                        // don't respawn a tile on top of anything
+                        let tile_rect = body.tile_rect(*index);
+                        if !world
+                            .query::<(&Actor, &IntRect)>()
+                            .iter()
+                            .any(|(_, (_, rect))| rect.intersects(&tile_rect))
+                        {
+                            restored.push(*index);
+                        }
+                    }
+                    Some(n) => *n -= 1,
+                    None => (),
+                }
+            }
+
+            if collapsed.is_empty() && restored.is_empty() {
+                continue;
+            }
+            for index in collapsed {
+                br.crumbling.remove(&index);
+                br.break_tile(body, index, true, buffer);
+            }
+            for index in restored {
+                let tile = br.broken.remove(&index).unwrap();
+                body.data[index] = tile.flags;
+            }
+            changed.push(id);
+        }
+        for id in changed {
+            refresh_body(&world, &mut resources.body_index, id);
+        }
+    }
+}
+
+// break any destructible tiles overlapping the given rect, returning whether any were broken
+pub fn damage_tiles(
+    world: &World,
+    body_index: &mut SpatialIndex,
+    buffer: &mut CommandBuffer,
+    rect: &IntRect,
+) -> bool {
+    let mut changed = Vec::new();
+    for id in body_index.entities(rect).iter() {
+        let mut q = world
+            .query_one::<(&mut TileBody, &mut Breakable)>(*id)
+            .unwrap();
+        if let Some((body, br)) = q.get() {
+            let mut body_changed = false;
+            for index in body.tiles_in_rect(rect) {
+                if body.data[index].is_destructible() {
+                    br.break_tile(body, index, false, buffer);
+                    body_changed = true;
+                }
+            }
+            if body_changed {
+                changed.push(*id);
+            }
+        }
+    }
+    for id in &changed {
+        refresh_body(world, body_index, *id);
+    }
+    !changed.is_empty()
+}
+
+// bring the spatial index and obscurers back in line with a body's tiles
+fn refresh_body(world: &World, body_index: &mut SpatialIndex, id: Entity) {
+    let mut body = world.get::<&mut TileBody>(id).unwrap();
+    let solid = body
+        .data
+        .iter()
+        .any(|flags| flags.is_blocker() || flags.is_platform());
+    if solid != body.indexed {
+        if solid {
+            body_index.insert_at(id, &body.get_rect());
+        } else {
+            body_index.remove_at(id, &body.get_rect());
+        }
+        body.indexed = solid;
+    }
+    drop(body);
+    update_obscurers(world, id);
+}
@@ -9,6 +9,16 @@ pub fn add_camera(world: &mut World, player_pos: Vec2) -> Vec2 {
     pos
 }
 
+// snap the camera straight to the player, e.g. after respawning somewhere else
+pub fn reset_camera(world: &World, player_pos: Vec2) -> Vec2 {
+    let pos = vec2(player_pos.x, player_pos.y - CAMERA_FLOOR_OFFSET);
+    for (_, (cam, v)) in world.query::<(&mut PlayerCamera, &mut Vec2)>().iter() {
+        cam.floor = pos.y;
+        *v = pos;
+    }
+    pos
+}
+
 pub struct PlayerCamera {
     floor: f32,
 }
@@ -0,0 +1,112 @@
+use crate::camera::reset_camera;
+use crate::door::KeyColor;
+use crate::physics::IntRect;
+use crate::pickup::PickupRecord;
+use crate::player::add_player;
+use crate::resources::{Inventory, SceneResources};
+use crate::weapon::upgraded_weapon;
+use enum_map::EnumMap;
+use hecs::{Entity, World};
+use std::collections::HashMap;
+
+pub struct Checkpoint {
+    pub name: String,
+    pub reached: bool,
+}
+
+pub fn add_checkpoint(world: &mut World, name: String, x: i32, y: i32) -> Entity {
+    let rect = IntRect::new(x - 8, y - 32, 16, 32);
+    world.spawn((
+        rect,
+        Checkpoint {
+            name,
+            reached: false,
+        },
+    ))
+}
+
+// everything needed to put the player back the way they were at the checkpoint.
+// the rest of the world is left as it is, so dead enemies stay dead, except that
+// pickups taken since are put back, or keys and abilities could be lost for good
+#[derive(Clone)]
+pub struct CheckpointState {
+    x: i32,
+    y: i32,
+    inventory: Inventory,
+    keys: EnumMap<KeyColor, bool>,
+    // keyed by the pickup's entity, which may since have been despawned
+    pub taken: HashMap<Entity, PickupRecord>,
+}
+
+impl Checkpoint {
+    pub fn update(resources: &mut SceneResources) {
+        let reached = {
+            let world = resources.world_ref.lock().unwrap();
+            let p_rect = match world.get::<&IntRect>(resources.player_id) {
+                Ok(r) => r.clone(),
+                Err(_) => return,
+            };
+            let mut reached = None;
+            for (_, (rect, cp)) in world.query::<(&IntRect, &mut Checkpoint)>().iter() {
+                if !cp.reached && rect.intersects(&p_rect) {
+                    cp.reached = true;
+                    resources
+                        .triggers
+                        .insert(format!("{}_reached", cp.name).to_owned());
+                    // respawn standing on the checkpoint's base
+                    reached = Some((rect.x + rect.w / 2, rect.y + rect.h));
+                }
+            }
+            reached
+        };
+        if let Some((x, y)) = reached {
+            resources.checkpoint = Some(CheckpointState {
+                x,
+                y,
+                inventory: resources.persist_inventory(),
+                keys: resources.keys,
+                taken: HashMap::new(),
+            });
+            resources.messages.add("Checkpoint reached.".to_owned());
+        }
+    }
+}
+
+// returns false if there is no checkpoint, in which case the level needs restarting
+pub fn respawn_at_checkpoint(resources: &mut SceneResources) -> bool {
+    let cp = match &resources.checkpoint {
+        Some(cp) => cp.clone(),
+        None => return false,
+    };
+    let mut world = resources.world_ref.lock().unwrap();
+    for (id, record) in &cp.taken {
+        // swapped weapons are still lying around, holding something else
+        world.despawn(*id).ok();
+        record.restore(&mut world);
+    }
+    // ready to be recorded again if they're taken again
+    if let Some(cp) = &mut resources.checkpoint {
+        cp.taken.clear();
+    }
+    // the old body is left lying around after death, so get rid of it
+    world.despawn(resources.player_id).ok();
+    resources.player_id = add_player(&mut world, cp.x, cp.y, &cp.inventory);
+    let eye = world.get::<&IntRect>(resources.player_id).unwrap().centre();
+    resources.eye_pos = eye;
+    resources.camera_pos = reset_camera(&world, eye);
+    resources.dynamic_index.update(&world);
+    drop(world);
+    resources.weapons = cp
+        .inventory
+        .weapon_types
+        .iter()
+        .map(|(t, upgrades)| upgraded_weapon(&resources.weapon_defs, *t, *upgrades))
+        .collect();
+    resources.ammo = cp.inventory.ammo;
+    resources.abilities = cp.inventory.abilities;
+    resources.keys = cp.keys;
+    resources.powerups.clear_timed();
+    resources.death_timer = None;
+    resources.triggers.insert("player_respawn".to_owned());
+    true
+}
@@ -0,0 +1,85 @@
+// helpers shared by the parsers for the text data files (weapons, enemies, movement)
+// each line is a "key value" pair, an [id] starting a new section, or blank.
+// anything after a # is a comment
+
+pub enum Line<'a> {
+    Section(&'a str),
+    // the value is the rest of the line, so can contain spaces, and is empty for
+    // a key on its own
+    Setting(&'a str, &'a str),
+}
+
+// every line with something on it, stripped of comments, for use in error messages,
+// along with what it says
+pub fn lines<'a>(text: &'a str) -> impl Iterator<Item = (&'a str, Line<'a>)> {
+    text.lines().filter_map(|line| {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            return None;
+        }
+        if let Some(id) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            return Some((line, Line::Section(id.trim())));
+        }
+        let (key, value) = match line.split_once(char::is_whitespace) {
+            Some((key, value)) => (key, value.trim()),
+            None => (line, ""),
+        };
+        Some((line, Line::Setting(key, value)))
+    })
+}
+
+// the setters return false if the value is no good, leaving the field as it was
+
+pub fn set<T: std::str::FromStr>(field: &mut T, value: &str) -> bool {
+    match value.parse() {
+        Ok(v) => {
+            *field = v;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+pub fn set_value<T>(field: &mut T, value: T) -> bool {
+    *field = value;
+    true
+}
+
+// for settings that get divided by
+pub fn set_nonzero(field: &mut u8, value: &str) -> bool {
+    match value.parse() {
+        Ok(v) if v > 0 => set_value(field, v),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_skip_comments_and_blanks() {
+        let text = "# a comment\n\n  [ shotgun ]  \nname double barrel # trailing\ndrag\n";
+        let lines: Vec<(&str, Line)> = lines(text).collect();
+        assert_eq!(lines.len(), 3);
+        assert!(matches!(
+            lines[0],
+            ("[ shotgun ]", Line::Section("shotgun"))
+        ));
+        assert!(matches!(
+            lines[1],
+            ("name double barrel", Line::Setting("name", "double barrel"))
+        ));
+        assert!(matches!(lines[2], ("drag", Line::Setting("drag", ""))));
+    }
+
+    #[test]
+    fn bad_values_are_left_alone() {
+        let mut n: u8 = 5;
+        assert!(!set(&mut n, "lots"));
+        assert!(!set_nonzero(&mut n, "0"));
+        assert_eq!(n, 5);
+        assert!(set_nonzero(&mut n, "7"));
+        assert_eq!(n, 7);
+    }
+}
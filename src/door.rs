@@ -0,0 +1,106 @@
+use crate::input::{Input, VirtualKey};
+use crate::physics::{IntRect, PathMotion, PathMotionType, TileBody};
+use crate::resources::SceneResources;
+use enum_map::Enum;
+use hecs::CommandBuffer;
+use macroquad::color::{Color, BLUE, RED, YELLOW};
+
+const DOOR_SPEED: f32 = 2.0;
+
+#[derive(Enum, Copy, Clone, PartialEq, Eq)]
+pub enum KeyColor {
+    Red,
+    Blue,
+    Yellow,
+}
+
+impl KeyColor {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "red" => Some(Self::Red),
+            "blue" => Some(Self::Blue),
+            "yellow" => Some(Self::Yellow),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Red => "red",
+            Self::Blue => "blue",
+            Self::Yellow => "yellow",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Self::Red => RED,
+            Self::Blue => BLUE,
+            Self::Yellow => YELLOW,
+        }
+    }
+}
+
+// attached to a door layer that won't open without the right key
+pub struct LockedDoor {
+    pub name: String,
+    pub key: KeyColor,
+    open: bool,
+    // so the message isn't repeated every frame the player leans on the door
+    touching: bool,
+}
+
+impl LockedDoor {
+    pub fn new(name: String, key: KeyColor) -> Self {
+        Self {
+            name,
+            key,
+            open: false,
+            touching: false,
+        }
+    }
+
+    pub fn update(resources: &mut SceneResources, buffer: &mut CommandBuffer, input: &Input) {
+        let world = resources.world_ref.lock().unwrap();
+        let p_rect = match world.get::<&IntRect>(resources.player_id) {
+            // the door is solid so grow the player's rect a little to see if they're against it
+            Ok(r) => IntRect::new(r.x - 1, r.y - 1, r.w + 2, r.h + 2),
+            Err(_) => return,
+        };
+        let interact = input.is_pressed(VirtualKey::Interact);
+        for (id, (body, door)) in world.query::<(&TileBody, &mut LockedDoor)>().iter() {
+            if door.open {
+                continue;
+            }
+            let touching = body.get_rect().intersects(&p_rect);
+            if touching && resources.keys[door.key] {
+                door.open = true;
+                // slide up out of the way, from wherever the door is now
+                let h = body.get_rect().h as f32;
+                let dx = body.x as f32 - body.base_pos.x;
+                let dy = body.y as f32 - body.base_pos.y;
+                buffer.insert_one(
+                    id,
+                    PathMotion::new(
+                        body.x as f32,
+                        body.y as f32,
+                        &[(dx, dy - h)],
+                        DOOR_SPEED,
+                        PathMotionType::Static,
+                    ),
+                );
+                resources
+                    .triggers
+                    .insert(format!("{}_unlock", door.name).to_owned());
+                resources
+                    .messages
+                    .add(format!("Opened the {} door.", door.key.name()));
+            } else if touching && (!door.touching || interact) {
+                resources
+                    .messages
+                    .add(format!("You need the {} key.", door.key.name()));
+            }
+            door.touching = touching;
+        }
+    }
+}
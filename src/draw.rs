@@ -1,13 +1,20 @@
-use crate::enemy::{EnemyHittable, ParrotKind};
+use crate::breakable::Breakable;
+use crate::checkpoint::Checkpoint;
+use crate::enemy::{EnemyHittable, SpawnerBehaviour, TurretBehaviour, TURRET_BARREL};
+use crate::enemy_defs::{EnemyDef, EnemyType};
+use crate::melee::MELEE_FRAMES;
 use crate::physics::{IntRect, TileBody};
 use crate::pickup::{Pickup, PickupType, WeaponPickup};
 use crate::resources::{GlobalAssets, SceneResources};
-use crate::switch::Switch;
+use crate::switch::{PressurePlate, Switch};
 use crate::vfx::ZapFlash;
-use crate::weapon::{weapon_sprite_frame, AmmoType};
+use crate::weapon::AmmoType;
 use hecs::World;
 use macroquad::prelude::*;
 
+pub(crate) const ARMOR_COLOR: Color = SKYBLUE;
+pub(crate) const OVERHEAL_COLOR: Color = MAGENTA;
+
 pub(crate) struct ColorRect {
     color: Color,
 }
@@ -25,8 +32,15 @@ pub(crate) struct PlayerSprite {
     pub flipped: bool,
     pub blink: bool,
     pub muzzle_flash: u8,
+    pub dashing: bool,
+    // counts down through a somersault after a double jump
+    pub spin: u8,
+    // counts down through a melee swing
+    pub swing: u8,
 }
 
+pub(crate) const PLAYER_SPIN_FRAMES: u8 = 12;
+
 impl PlayerSprite {
     pub fn new() -> Self {
         Self {
@@ -35,40 +49,59 @@ impl PlayerSprite {
             flipped: true,
             blink: false,
             muzzle_flash: 0,
+            dashing: false,
+            spin: 0,
+            swing: 0,
         }
     }
 }
 
-pub(crate) struct DogSprite {
-    pub n: i32,
-    pub flipped: bool,
-}
-
-impl DogSprite {
-    pub fn new() -> Self {
-        Self {
-            n: 0,
-            flipped: false,
-        }
-    }
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum EnemyAnim {
+    Idle,
+    Walk,
+    Attack,
 }
 
-pub(crate) struct ParrotSprite {
-    pub kind: ParrotKind,
-    pub frame: u8,
+// picks frames from the enemy's sprite sheet as listed in its definition
+pub(crate) struct EnemySprite {
+    pub typ: EnemyType,
+    anim: EnemyAnim,
+    // frames since the animation started
+    n: u32,
     pub flipped: bool,
     pub muzzle_flash: Option<u8>,
 }
 
-impl ParrotSprite {
-    pub fn new(kind: ParrotKind) -> Self {
+impl EnemySprite {
+    pub fn new(typ: EnemyType) -> Self {
         Self {
-            kind,
-            frame: 0,
+            typ,
+            anim: EnemyAnim::Idle,
+            n: 0,
             flipped: false,
             muzzle_flash: None,
         }
     }
+
+    // called once per frame, restarting the animation if it has changed
+    pub fn set_anim(&mut self, anim: EnemyAnim) {
+        if anim == self.anim {
+            self.n += 1;
+        } else {
+            self.anim = anim;
+            self.n = 0;
+        }
+    }
+
+    fn frame(&self, def: &EnemyDef) -> u8 {
+        let frames = match self.anim {
+            EnemyAnim::Idle => &def.idle_frames,
+            EnemyAnim::Walk => &def.walk_frames,
+            EnemyAnim::Attack => &def.attack_frames,
+        };
+        frames[(self.n / def.frame_time as u32) as usize % frames.len()]
+    }
 }
 
 pub(crate) struct PickupSprite {}
@@ -101,6 +134,7 @@ pub(crate) fn draw_tiles(world: &mut World, resources: &SceneResources) {
     let cam = resources.camera_pos;
     for id in &resources.draw_order {
         let chunk = world.get::<&TileBody>(*id).unwrap();
+        let breakable = world.get::<&Breakable>(*id).ok();
         let cx_min = ((cam.x as i32 - chunk.x - crate::RENDER_W as i32 / 2) / chunk.size).max(0);
         let cx_max = ((cam.x as i32 - chunk.x + crate::RENDER_W as i32 / 2) / chunk.size)
             .min(chunk.width - 1);
@@ -116,9 +150,10 @@ pub(crate) fn draw_tiles(world: &mut World, resources: &SceneResources) {
                     let tsi = &resources.tileset_info;
                     let sx = (chunk.tiles[ii] as u32 % tsi.columns) * tsi.tile_width;
                     let sy = (chunk.tiles[ii] as u32 / tsi.columns) * tsi.tile_height;
+                    let shake = breakable.as_ref().map_or(0.0, |b| b.shake_offset(ii));
                     draw_texture_ex(
                         tsi.texture,
-                        tx as f32,
+                        tx as f32 + shake,
                         ty as f32,
                         WHITE,
                         DrawTextureParams {
@@ -176,19 +211,50 @@ pub(crate) fn draw_sprites(world: &mut World, resources: &SceneResources, assets
         }
     }
 
+    for (_, (rect, cp)) in world.query::<(&IntRect, &Checkpoint)>().iter() {
+        if rect.intersects(&camera_rect) {
+            // a pole with a flag that changes colour once it has been reached
+            let color = if cp.reached { GREEN } else { RED };
+            draw_rectangle(rect.x as f32, rect.y as f32, 2.0, rect.h as f32, GRAY);
+            draw_rectangle(rect.x as f32 + 2.0, rect.y as f32, 10.0, 6.0, color);
+        }
+    }
+
+    for (_, (rect, plate)) in world.query::<(&IntRect, &PressurePlate)>().iter() {
+        if rect.intersects(&camera_rect) {
+            let h = if plate.pressed { 2 } else { rect.h };
+            draw_rectangle(
+                rect.x as f32,
+                (rect.y + rect.h - h) as f32,
+                rect.w as f32,
+                h as f32,
+                GRAY,
+            );
+        }
+    }
+
     for (_, (rect, p, _spr)) in world.query::<(&IntRect, &Pickup, &PickupSprite)>().iter() {
         if rect.intersects(&camera_rect) {
-            let y = match p.typ {
-                PickupType::Heart => 0.0,
-                PickupType::Ammo(AmmoType::Cell, _) => 16.0,
-                PickupType::Ammo(AmmoType::Shell, _) => 32.0,
-                PickupType::Ammo(AmmoType::Rocket, _) => 48.0,
+            let (y, color) = match p.typ {
+                PickupType::Heart => (0.0, WHITE),
+                PickupType::Ammo(AmmoType::Cell, _) => (16.0, WHITE),
+                PickupType::Ammo(AmmoType::Shell, _) => (32.0, WHITE),
+                PickupType::Ammo(AmmoType::Rocket, _) => (48.0, WHITE),
+                // the health upgrades are recoloured hearts
+                PickupType::Armor(_) => (0.0, ARMOR_COLOR),
+                PickupType::MaxHealth => (0.0, GOLD),
+                PickupType::MegaHealth => (0.0, OVERHEAL_COLOR),
+                // drawn as a ColorRect instead
+                PickupType::Ability(_)
+                | PickupType::Key(_)
+                | PickupType::PowerUp(_)
+                | PickupType::Upgrade(_) => continue,
             };
             draw_texture_ex(
                 assets.pickup_sprite,
                 rect.x as f32,
                 rect.y as f32,
-                WHITE,
+                color,
                 DrawTextureParams {
                     source: Some(Rect::new(0.0, y, 16.0, 16.0)),
                     ..Default::default()
@@ -199,7 +265,7 @@ pub(crate) fn draw_sprites(world: &mut World, resources: &SceneResources, assets
 
     for (_, (rect, w)) in world.query::<(&IntRect, &WeaponPickup)>().iter() {
         if rect.intersects(&camera_rect) {
-            let frame = weapon_sprite_frame(w.typ);
+            let frame = resources.weapon_defs.get(w.typ).sprite;
             draw_texture_ex(
                 assets.weapon_sprite,
                 rect.x as f32,
@@ -221,7 +287,8 @@ pub(crate) fn draw_sprites(world: &mut World, resources: &SceneResources, assets
                 rect.y as f32,
                 WHITE,
                 DrawTextureParams {
-                    dest_size: Some(vec2(8.0, 5.0)),
+                    // charged shots are stretched to fit
+                    dest_size: Some(vec2(rect.w as f32, rect.h as f32)),
                     source: Some(Rect::new(0.0, 2.0, 8.0, 5.0)),
                     ..Default::default()
                 },
@@ -237,7 +304,7 @@ pub(crate) fn draw_sprites(world: &mut World, resources: &SceneResources, assets
             draw_texture_ex(
                 assets.zap_sprite,
                 rect.x as f32 + if spr.flipped { 11.0 } else { -6.0 },
-                rect.y as f32 + 9.0,
+                (rect.y + rect.h) as f32 - 15.0,
                 WHITE,
                 DrawTextureParams {
                     dest_size: Some(vec2(9.0, 9.0)),
@@ -246,41 +313,83 @@ pub(crate) fn draw_sprites(world: &mut World, resources: &SceneResources, assets
                 },
             );
         }
-        let frame = if spr.firing { 2 } else { spr.n * 5 % 2 };
+        let frame = if spr.firing || spr.swing > 0 {
+            2
+        } else {
+            spr.n * 5 % 2
+        };
+        let dir = if spr.flipped { 1.0 } else { -1.0 };
+        let rotation = spr.spin as f32 / PLAYER_SPIN_FRAMES as f32 * -dir * std::f32::consts::TAU;
+        let params = DrawTextureParams {
+            // squash the sprite down to fit when crouching
+            dest_size: Some(vec2(16.0, rect.h as f32)),
+            source: Some(Rect::new(0.0, 24.0 * frame as f32, 16.0, 24.0)),
+            rotation,
+            flip_x: spr.flipped,
+            ..Default::default()
+        };
+        if spr.dashing {
+            // fading afterimages trailing behind
+            for i in 1..=2 {
+                draw_texture_ex(
+                    assets.player_sprite,
+                    (rect.x - 1) as f32 - dir * 6.0 * i as f32,
+                    rect.y as f32,
+                    Color::new(1.0, 1.0, 1.0, 0.5 / i as f32),
+                    params.clone(),
+                );
+            }
+        }
         draw_texture_ex(
             assets.player_sprite,
             (rect.x - 1) as f32,
             rect.y as f32,
             WHITE,
-            DrawTextureParams {
-                dest_size: Some(vec2(16.0, 24.0)),
-                source: Some(Rect::new(0.0, 24.0 * frame as f32, 16.0, 24.0)),
-                flip_x: spr.flipped,
-                ..Default::default()
-            },
+            params,
         );
+        if spr.swing > 0 {
+            // a slash sweeping down in front, with a fading tail
+            let (cx, cy) = (rect.centre().x, rect.y as f32 + 10.0);
+            let progress = 1.0 - spr.swing as f32 / MELEE_FRAMES as f32;
+            let a = -1.2 + 2.4 * progress;
+            for i in 0..4 {
+                let a0 = a - 0.2 * (i + 1) as f32;
+                let a1 = a - 0.2 * i as f32;
+                draw_line(
+                    cx + dir * a0.cos() * 14.0,
+                    cy + a0.sin() * 14.0,
+                    cx + dir * a1.cos() * 14.0,
+                    cy + a1.sin() * 14.0,
+                    2.0,
+                    Color::new(1.0, 1.0, 1.0, 1.0 - 0.25 * i as f32),
+                );
+            }
+        }
     }
 
     for (_, (rect, spr, hittable)) in world
-        .query::<(&IntRect, &ParrotSprite, &EnemyHittable)>()
+        .query::<(&IntRect, &EnemySprite, &EnemyHittable)>()
         .iter()
     {
         if rect.intersects(&camera_rect) {
+            let def = resources.enemy_defs.get(spr.typ);
+            let tex = match def.texture {
+                Some(tex) => tex,
+                None => continue,
+            };
             if hittable.was_hit {
                 gl_use_material(assets.flash_material);
             }
-            let tex = match spr.kind {
-                ParrotKind::Laser => assets.parrot_sprite,
-                ParrotKind::Cannon => assets.parrot_sprite2,
-            };
+            let (fw, fh) = (def.frame_width, def.frame_height);
+            // centred on the hitbox and standing on its bottom edge
             draw_texture_ex(
                 tex,
-                rect.x as f32,
-                rect.y as f32,
+                rect.x as f32 + (rect.w as f32 - fw) / 2.0,
+                (rect.y + rect.h) as f32 - fh,
                 WHITE,
                 DrawTextureParams {
-                    dest_size: Some(vec2(24.0, 24.0)),
-                    source: Some(Rect::new(0.0, 24.0 * spr.frame as f32, 24.0, 24.0)),
+                    dest_size: Some(vec2(fw, fh)),
+                    source: Some(Rect::new(0.0, fh * spr.frame(def) as f32, fw, fh)),
                     flip_x: spr.flipped,
                     ..Default::default()
                 },
@@ -288,7 +397,12 @@ pub(crate) fn draw_sprites(world: &mut World, resources: &SceneResources, assets
             if let Some(mf) = spr.muzzle_flash {
                 draw_texture_ex(
                     assets.zap_sprite,
-                    rect.x as f32 + if spr.flipped { 16.0 } else { -1.0 },
+                    rect.x as f32
+                        + if spr.flipped {
+                            rect.w as f32 - 8.0
+                        } else {
+                            -1.0
+                        },
                     rect.y as f32 + 6.0,
                     WHITE,
                     DrawTextureParams {
@@ -302,27 +416,49 @@ pub(crate) fn draw_sprites(world: &mut World, resources: &SceneResources, assets
         }
     }
 
-    for (_, (rect, spr, hittable)) in world
-        .query::<(&IntRect, &DogSprite, &EnemyHittable)>()
+    // turrets and spawners have no sprites yet
+    for (_, (rect, turret, hittable)) in world
+        .query::<(&IntRect, &TurretBehaviour, &EnemyHittable)>()
         .iter()
     {
         if rect.intersects(&camera_rect) {
-            if hittable.was_hit {
-                gl_use_material(assets.flash_material);
-            }
-            draw_texture_ex(
-                assets.dog_sprite,
+            let c = rect.centre();
+            let end = c + turret.aim_vector() * TURRET_BARREL;
+            draw_line(c.x, c.y, end.x, end.y, 4.0, DARKGRAY);
+            let color = if hittable.was_hit { WHITE } else { GRAY };
+            draw_rectangle(
                 rect.x as f32,
                 rect.y as f32,
-                WHITE,
-                DrawTextureParams {
-                    dest_size: Some(vec2(24.0, 16.0)),
-                    source: Some(Rect::new(0.0, 16.0 * (spr.n / 5 % 2) as f32, 24.0, 16.0)),
-                    flip_x: spr.flipped,
-                    ..Default::default()
-                },
+                rect.w as f32,
+                rect.h as f32,
+                color,
+            );
+            draw_circle(c.x, c.y, 2.0, RED);
+        }
+    }
+
+    for (_, (rect, spawner, hittable)) in world
+        .query::<(&IntRect, &SpawnerBehaviour, &EnemyHittable)>()
+        .iter()
+    {
+        if rect.intersects(&camera_rect) {
+            let color = if hittable.was_hit { WHITE } else { DARKPURPLE };
+            draw_rectangle(
+                rect.x as f32,
+                rect.y as f32,
+                rect.w as f32,
+                rect.h as f32,
+                color,
+            );
+            // a core that glows brighter as the next enemy gets closer
+            let glow = Color::new(1.0, 0.2, 1.0, 0.3 + 0.7 * spawner.charge());
+            draw_rectangle(
+                (rect.x + 4) as f32,
+                (rect.y + 4) as f32,
+                (rect.w - 8) as f32,
+                (rect.h - 8) as f32,
+                glow,
             );
-            gl_use_default_material();
         }
     }
 
@@ -340,3 +476,59 @@ pub(crate) fn draw_sprites(world: &mut World, resources: &SceneResources, assets
         );
     }
 }
+
+// debug view of how crowded each spatial index bucket is, with the things sharing
+// the player's bucket outlined
+pub(crate) fn draw_index_overlay(world: &World, resources: &SceneResources) {
+    for (rect, n) in resources.body_index.occupancy() {
+        let a = (n as f32 / 16.0).min(1.0) * 0.5;
+        draw_rectangle(
+            rect.x as f32,
+            rect.y as f32,
+            rect.w as f32,
+            rect.h as f32,
+            Color::new(0.0, 0.0, 1.0, a),
+        );
+        draw_rectangle_lines(
+            rect.x as f32,
+            rect.y as f32,
+            rect.w as f32,
+            rect.h as f32,
+            1.0,
+            BLUE,
+        );
+    }
+    for (rect, n) in resources.dynamic_index.occupancy() {
+        let a = (n as f32 / 16.0).min(1.0) * 0.5;
+        draw_rectangle(
+            rect.x as f32,
+            rect.y as f32,
+            rect.w as f32,
+            rect.h as f32,
+            Color::new(1.0, 0.0, 0.0, a),
+        );
+        draw_text(
+            &format!("{}", n),
+            rect.x as f32 + 2.0,
+            rect.y as f32 + 10.0,
+            12.0,
+            RED,
+        );
+    }
+    let (px, py) = match world.get::<&IntRect>(resources.player_id) {
+        Ok(p_rect) => (p_rect.x + p_rect.w / 2, p_rect.y + p_rect.h / 2),
+        Err(_) => return,
+    };
+    for id in resources.dynamic_index.entities_at_point(px, py).iter() {
+        if let Ok(rect) = world.get::<&IntRect>(*id) {
+            draw_rectangle_lines(
+                rect.x as f32,
+                rect.y as f32,
+                rect.w as f32,
+                rect.h as f32,
+                1.0,
+                YELLOW,
+            );
+        }
+    }
+}
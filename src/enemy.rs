@@ -1,55 +1,130 @@
 use std::cmp::Ordering;
+use std::collections::HashMap;
 
-use crate::draw::{DogSprite, ParrotSprite};
-use crate::physics::{collide_any, Actor, IntRect};
+use crate::draw::{EnemyAnim, EnemySprite};
+use crate::enemy_defs::{AttackKind, BehaviourKind, EnemyDefs, EnemyType};
+use crate::health::{Damage, DamageType, DeathEvent, Health, Victim};
+use crate::index::{Indexed, SpatialIndex};
+use crate::physics::{collide_any, Actor, Heavy, Impulse, IntRect};
 use crate::player::Controller;
 use crate::projectile::{make_enemy_fireball, make_enemy_laser};
 use crate::resources::SceneResources;
-use crate::vfx::create_explosion;
-use hecs::{CommandBuffer, Entity, World};
+use crate::vfx::{create_explosion, ZapFlash};
+use hecs::{CommandBuffer, Entity, EntityBuilder, World};
 use macroquad::prelude::*;
 
-#[derive(PartialEq, Eq, Copy, Clone)]
-pub enum EnemyKind {
-    Dog,
-    JumpyDog,
-    SpiderParrot(ParrotKind),
+// which way a turret faces, away from the wall or ceiling it's fixed to
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum TurretDirection {
+    Up,
+    Down,
+    Left,
+    Right,
 }
 
-pub fn add_enemy(world: &mut World, kind: EnemyKind, x: i32, y: i32) {
-    let h = match kind {
-        EnemyKind::SpiderParrot(_) => 24,
-        _ => 16,
-    };
-    let rect = IntRect::new(x - 12, y - h, 24, h);
-    let actor = Actor::new(&rect, 0.4);
-    let hp = match kind {
-        EnemyKind::SpiderParrot(_) => 7,
-        _ => 3,
+impl TurretDirection {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "up" => Some(Self::Up),
+            "down" => Some(Self::Down),
+            "left" => Some(Self::Left),
+            "right" => Some(Self::Right),
+            _ => None,
+        }
+    }
+
+    pub fn vector(self) -> Vec2 {
+        match self {
+            Self::Up => vec2(0.0, -1.0),
+            Self::Down => vec2(0.0, 1.0),
+            Self::Left => vec2(-1.0, 0.0),
+            Self::Right => vec2(1.0, 0.0),
+        }
+    }
+
+    // (x, y) is the middle of the side against the wall. wall turrets lie on their side
+    fn mounted_rect(self, x: i32, y: i32, w: i32, h: i32) -> IntRect {
+        match self {
+            Self::Up => IntRect::new(x - w / 2, y - h, w, h),
+            Self::Down => IntRect::new(x - w / 2, y, w, h),
+            Self::Left => IntRect::new(x - h, y - w / 2, h, w),
+            Self::Right => IntRect::new(x, y - w / 2, h, w),
+        }
+    }
+}
+
+// things that can be set for each enemy placed in a map, defaulting to its definition
+pub struct EnemySettings {
+    pub direction: TurretDirection,
+    pub rate: u8,
+    pub spawn: Option<EnemyType>,
+    pub spawn_max: u8,
+}
+
+impl EnemySettings {
+    pub fn new(defs: &EnemyDefs, typ: EnemyType) -> Self {
+        let def = defs.get(typ);
+        Self {
+            direction: TurretDirection::Up,
+            rate: def.rate,
+            spawn: defs.find(&def.spawn),
+            spawn_max: def.spawn_max,
+        }
+    }
+}
+
+// (x, y) is the middle of the enemy's bottom edge, or for turrets the side they're fixed by
+pub fn add_enemy(
+    world: &mut World,
+    defs: &EnemyDefs,
+    typ: EnemyType,
+    x: i32,
+    y: i32,
+    settings: &EnemySettings,
+) {
+    world.spawn(enemy_builder(defs, typ, x, y, settings).build());
+}
+
+fn enemy_builder(
+    defs: &EnemyDefs,
+    typ: EnemyType,
+    x: i32,
+    y: i32,
+    settings: &EnemySettings,
+) -> EntityBuilder {
+    let def = defs.get(typ);
+    let rect = match def.behaviour {
+        BehaviourKind::Turret => settings.direction.mounted_rect(x, y, def.width, def.height),
+        _ => IntRect::new(x - def.width / 2, y - def.height, def.width, def.height),
     };
-    let hittable = EnemyHittable::new(hp);
-    let dmg = EnemyContactDamage::new();
-    if let EnemyKind::SpiderParrot(pk) = kind {
-        world.spawn((
-            kind,
-            ParrotBehaviour::new(pk),
-            rect,
-            crate::draw::ParrotSprite::new(pk),
-            actor,
-            hittable,
-            dmg,
-        ));
-    } else {
-        world.spawn((
-            kind,
-            DogBehaviour::new(),
-            rect,
-            crate::draw::DogSprite::new(),
-            actor,
-            hittable,
-            dmg,
-        ));
+    let home = rect.centre();
+    let health = Health::new(def.hp).with_resistances(def.resistances);
+    let mut b = EntityBuilder::new();
+    match def.behaviour {
+        BehaviourKind::Hop | BehaviourKind::Patrol | BehaviourKind::Fly => {
+            let mut actor = Actor::new(&rect, 0.4);
+            actor.gravity = def.behaviour != BehaviourKind::Fly;
+            b.add_bundle((actor, EnemySprite::new(typ), Heavy {}));
+        }
+        // these don't move, so are drawn without sprites
+        BehaviourKind::Turret | BehaviourKind::Spawner => (),
     }
+    b.add_bundle((typ, rect, health, EnemyHittable::new(), Indexed {}));
+    if def.contact_damage > 0 {
+        b.add(EnemyContactDamage::new(def.contact_damage));
+    }
+    match def.behaviour {
+        BehaviourKind::Hop => b.add(HopBehaviour::new()),
+        BehaviourKind::Patrol => b.add(PatrolBehaviour::new()),
+        BehaviourKind::Fly => b.add(FlyBehaviour::new(home)),
+        BehaviourKind::Turret => b.add(TurretBehaviour::new(settings.direction, settings.rate)),
+        BehaviourKind::Spawner => b.add(SpawnerBehaviour::new(
+            settings.spawn,
+            settings.rate,
+            settings.spawn_max,
+        )),
+    };
+    b
 }
 
 fn with_prob(p: f32) -> bool {
@@ -74,36 +149,63 @@ fn player_y(world: &World, player_id: Entity) -> Option<f32> {
         .ok()
 }
 
+const STUN_FRAMES: u8 = 10;
+const CONTACT_KNOCKBACK: f32 = 6.0;
+
 pub struct EnemyHittable {
-    pub hp: u16,
     pub was_hit: bool,
+    knockback: Option<Impulse>,
+    stun_timer: u8,
 }
 
 impl EnemyHittable {
-    pub fn new(hp: u16) -> Self {
-        Self { hp, was_hit: false }
+    pub fn new() -> Self {
+        Self {
+            was_hit: false,
+            knockback: None,
+            stun_timer: 0,
+        }
     }
 
-    pub fn hurt(&mut self, amount: u16) {
-        self.hp -= amount.min(self.hp);
+    pub fn hurt(&mut self, health: &mut Health, dmg: Damage) {
+        health.damage(dmg);
         self.was_hit = true;
     }
+
+    // several hits in one frame (e.g. a shotgun blast) add up
+    pub fn knock_back(&mut self, impulse: Impulse) {
+        self.knockback = Some(match self.knockback {
+            Some(k) => Impulse {
+                force: k.force + impulse.force,
+                ..k
+            },
+            None => impulse,
+        });
+        self.stun_timer = STUN_FRAMES;
+    }
+
+    pub fn is_stunned(&self) -> bool {
+        self.stun_timer > 0
+    }
 }
 
-struct EnemyContactDamage {}
+// hurts the player on touch
+struct EnemyContactDamage {
+    amount: u16,
+}
 
 impl EnemyContactDamage {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(amount: u16) -> Self {
+        Self { amount }
     }
 }
 
-struct DogBehaviour {
+struct HopBehaviour {
     dir: f32,
     jump_y: Option<i32>,
 }
 
-impl DogBehaviour {
+impl HopBehaviour {
     pub fn new() -> Self {
         Self {
             dir: 0.0,
@@ -113,34 +215,34 @@ impl DogBehaviour {
 
     pub fn update(world: &World, resources: &SceneResources) {
         let player_x = player_x(world, resources.player_id);
-        for (_, (kind, actor, enemy, rect, spr)) in world
+        for (_, (typ, actor, enemy, rect, spr, hittable)) in world
             .query::<(
-                &EnemyKind,
+                &EnemyType,
                 &mut Actor,
-                &mut DogBehaviour,
+                &mut HopBehaviour,
                 &IntRect,
-                &mut DogSprite,
+                &mut EnemySprite,
+                &EnemyHittable,
             )>()
             .iter()
         {
+            if hittable.is_stunned() {
+                continue;
+            }
+            let def = resources.enemy_defs.get(*typ);
             if (actor.grounded || enemy.jump_y.is_some()) && with_prob(0.1) {
                 match player_x {
                     Some(x) if with_prob(0.7) => {
-                        enemy.dir = (x - rect.centre().x).signum() * 5.0;
+                        enemy.dir = (x - rect.centre().x).signum() * def.speed;
                     }
                     _ => {
-                        enemy.dir = 5.0 * rand_sign();
+                        enemy.dir = def.speed * rand_sign();
                     }
                 }
             }
             if actor.grounded {
-                let (jump_prob, jump_vel) = match kind {
-                    EnemyKind::Dog => (0.45, -6.0),
-                    EnemyKind::JumpyDog => (0.2, -8.0),
-                    _ => unreachable!(),
-                };
-                if with_prob(jump_prob) {
-                    actor.vy = jump_vel;
+                if with_prob(def.jump_chance) {
+                    actor.vy = -def.jump_speed;
                     enemy.jump_y = Some(rect.y);
                 } else {
                     enemy.jump_y = None;
@@ -161,161 +263,162 @@ impl DogBehaviour {
             if actor.vx > 0.0 {
                 spr.flipped = true
             }
-            spr.n += 1;
+            spr.set_anim(EnemyAnim::Walk);
         }
     }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
-pub enum ParrotKind {
-    Laser,
-    Cannon,
-}
-
-#[derive(Clone, Copy, PartialEq, Eq)]
-enum ParrotState {
+enum PatrolState {
     Wait,
     Move,
     Attack,
     Fall,
 }
 
-struct ParrotBehaviour {
-    kind: ParrotKind,
-    state: ParrotState,
+struct PatrolBehaviour {
+    state: PatrolState,
     state_timer: u8,
     attack_timer: u8,
     facing: i8,
 }
 
-impl ParrotBehaviour {
-    pub fn new(kind: ParrotKind) -> Self {
+impl PatrolBehaviour {
+    pub fn new() -> Self {
         Self {
-            kind,
-            state: ParrotState::Wait,
+            state: PatrolState::Wait,
             state_timer: 0,
             attack_timer: 0,
             facing: -1,
         }
     }
 
-    fn set_state(&mut self, state: ParrotState) {
+    fn set_state(&mut self, state: PatrolState) {
         self.state = state;
         self.state_timer = 0;
     }
 
+    // whether the player is level with us and there's nothing in the way
+    fn can_see_player(world: &World, resources: &SceneResources, rect: &IntRect) -> bool {
+        let (x, y) = match (
+            player_x(world, resources.player_id),
+            player_y(world, resources.player_id),
+        ) {
+            (Some(x), Some(y)) => (x, y),
+            _ => return false,
+        };
+        if (y - rect.centre().y).abs() >= 48.0 {
+            return false;
+        }
+        let min_x = (x + 16.0).min(rect.centre().x);
+        let max_x = (x - 16.0).max(rect.centre().x);
+        let r = IntRect::new(min_x as i32, rect.y, (max_x - min_x) as i32, rect.h);
+        !collide_any(world, &resources.body_index, &r)
+    }
+
     pub fn update(world: &World, resources: &SceneResources, buffer: &mut CommandBuffer) {
         let player_x = player_x(world, resources.player_id);
-        for (_, (actor, beh, rect, spr)) in world
+        for (_, (typ, actor, beh, rect, spr, hittable)) in world
             .query::<(
+                &EnemyType,
                 &mut Actor,
-                &mut ParrotBehaviour,
+                &mut PatrolBehaviour,
                 &IntRect,
-                &mut ParrotSprite,
+                &mut EnemySprite,
+                &EnemyHittable,
             )>()
             .iter()
         {
+            if hittable.is_stunned() {
+                spr.muzzle_flash = None;
+                continue;
+            }
+            let def = resources.enemy_defs.get(*typ);
             if !actor.grounded {
-                beh.set_state(ParrotState::Fall);
+                beh.set_state(PatrolState::Fall);
             }
 
-            spr.frame = 0;
+            let mut anim = EnemyAnim::Idle;
             spr.muzzle_flash = None;
-            let new_vx = actor.vx + 5.0 * beh.facing as f32;
+            let new_vx = actor.vx + def.speed * beh.facing as f32;
             match beh.state {
-                ParrotState::Fall => {
+                PatrolState::Fall => {
                     if actor.grounded {
-                        beh.set_state(ParrotState::Wait);
+                        beh.set_state(PatrolState::Wait);
                     }
                 }
-                ParrotState::Wait => {
+                PatrolState::Wait => {
                     if quad_rand::gen_range(10, 20) < beh.state_timer {
                         let is_facing_player = player_x.map_or(true, |x| {
                             (x - rect.centre().x).signum() == beh.facing as f32
                         });
                         if with_prob(0.5) {
-                            let will_attack = match beh.kind {
-                                ParrotKind::Laser => {
-                                    is_facing_player && beh.attack_timer == 0 && with_prob(0.85)
-                                }
-                                ParrotKind::Cannon => {
-                                    is_facing_player
-                                        && beh.attack_timer == 0
-                                        && player_y(world, resources.player_id)
-                                            .map_or(false, |y| (y - rect.centre().y).abs() < 48.0)
-                                        && player_x.map_or(false, |x| {
-                                            let min_x = (x + 16.0).min(rect.centre().x);
-                                            let max_x = (x - 16.0).max(rect.centre().x);
-                                            let w = max_x - min_x;
-                                            let r = IntRect::new(
-                                                min_x as i32,
-                                                rect.y,
-                                                w as i32,
-                                                rect.h,
-                                            );
-                                            !collide_any(world, &resources.body_index, &r)
-                                        })
-                                }
-                            };
+                            let can_shoot =
+                                matches!(def.attack, AttackKind::Laser | AttackKind::Fireball);
+                            let will_attack = can_shoot
+                                && is_facing_player
+                                && beh.attack_timer == 0
+                                && with_prob(def.attack_chance)
+                                && (!def.line_of_sight
+                                    || Self::can_see_player(world, resources, rect));
                             if will_attack {
-                                beh.set_state(ParrotState::Attack);
+                                beh.set_state(PatrolState::Attack);
                             } else {
                                 beh.facing = -beh.facing;
                                 beh.state_timer = 0;
                             }
-                        } else if !parrot_should_stop(world, resources, rect, new_vx) {
-                            beh.set_state(ParrotState::Move);
+                        } else if !patrol_should_stop(world, resources, rect, new_vx) {
+                            beh.set_state(PatrolState::Move);
                         }
                     }
                 }
-                ParrotState::Move => {
-                    spr.frame = (beh.state_timer / 2) % 2;
+                PatrolState::Move => {
+                    anim = EnemyAnim::Walk;
                     if beh.state_timer > 10 && with_prob(0.05)
-                        || parrot_should_stop(world, resources, rect, new_vx)
+                        || patrol_should_stop(world, resources, rect, new_vx)
                     {
-                        beh.set_state(ParrotState::Wait);
+                        beh.set_state(PatrolState::Wait);
                     } else {
                         actor.vx = new_vx;
                     }
                 }
-                ParrotState::Attack => {
-                    let (freq, limit, delay) = match beh.kind {
-                        ParrotKind::Laser => (6, 24, 30),
-                        ParrotKind::Cannon => (24, 12, 120),
-                    };
-                    spr.frame = 3;
+                PatrolState::Attack => {
+                    let freq = def.shot_interval;
+                    anim = EnemyAnim::Attack;
                     let mf = beh.state_timer % freq;
                     spr.muzzle_flash = if mf > 0 && mf < 5 { Some(mf) } else { None };
                     if beh.state_timer % freq == 1 {
-                        actor.vx -= beh.facing as f32 * 10.0;
+                        actor.vx -= beh.facing as f32 * def.recoil;
                         let new_x = rect.x + 7 + beh.facing as i32 * 6;
-                        match beh.kind {
-                            ParrotKind::Laser => {
+                        match def.attack {
+                            AttackKind::Laser => {
                                 let rect = IntRect::new(new_x, rect.y + 8, 8, 5);
-                                make_enemy_laser(buffer, rect, beh.facing as f32 * 4.0);
+                                make_enemy_laser(buffer, rect, beh.facing as f32 * def.shot_speed);
                             }
-                            ParrotKind::Cannon => {
+                            AttackKind::Fireball => {
                                 let rect = IntRect::new(new_x - 6, rect.y + 4, 12, 12);
                                 make_enemy_fireball(
                                     buffer,
                                     rect,
-                                    beh.facing as f32 * 2.0,
+                                    beh.facing as f32 * def.shot_speed,
                                     0.0,
                                     true,
                                 );
                             }
+                            _ => (),
                         }
                     }
                     if beh.state_timer % freq == freq - 1
-                        && parrot_off_edge(world, resources, rect, beh.facing)
+                        && patrol_off_edge(world, resources, rect, beh.facing)
                     {
-                        beh.set_state(ParrotState::Move);
-                    } else if beh.state_timer >= limit {
-                        beh.attack_timer = delay;
-                        beh.set_state(ParrotState::Wait);
+                        beh.set_state(PatrolState::Move);
+                    } else if beh.state_timer >= def.attack_length {
+                        beh.attack_timer = def.attack_cooldown;
+                        beh.set_state(PatrolState::Wait);
                     }
                 }
             }
+            spr.set_anim(anim);
 
             if beh.attack_timer > 0 {
                 beh.attack_timer -= 1;
@@ -330,7 +433,406 @@ impl ParrotBehaviour {
     }
 }
 
-fn parrot_should_stop(world: &World, resources: &SceneResources, rect: &IntRect, vx: f32) -> bool {
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FlyState {
+    Patrol,
+    Chase,
+    Attack,
+    Dive,
+    Return,
+}
+
+// how far ahead a flyer checks for walls when picking a way to go
+const FLY_LOOKAHEAD: f32 = 8.0;
+// directions to try either side of straight at the target, in radians
+const FLY_STEER_ANGLES: [f32; 7] = [0.0, 0.6, -0.6, 1.2, -1.2, 1.8, -1.8];
+// where flyers try to get to before attacking, relative to the player
+const FLY_SHOOT_DISTANCE: f32 = 64.0;
+const FLY_DIVE_HEIGHT: f32 = 64.0;
+
+struct FlyBehaviour {
+    state: FlyState,
+    state_timer: u8,
+    attack_timer: u8,
+    home: Vec2,
+    facing: i8,
+    dive_dir: Vec2,
+}
+
+impl FlyBehaviour {
+    pub fn new(home: Vec2) -> Self {
+        Self {
+            state: FlyState::Patrol,
+            state_timer: 0,
+            attack_timer: 0,
+            home,
+            facing: -1,
+            dive_dir: Vec2::ZERO,
+        }
+    }
+
+    fn set_state(&mut self, state: FlyState) {
+        self.state = state;
+        self.state_timer = 0;
+    }
+
+    pub fn update(world: &World, resources: &SceneResources, buffer: &mut CommandBuffer) {
+        let player = world
+            .get::<&IntRect>(resources.player_id)
+            .ok()
+            .map(|r| r.centre());
+        for (_, (typ, actor, beh, rect, spr, hittable)) in world
+            .query::<(
+                &EnemyType,
+                &mut Actor,
+                &mut FlyBehaviour,
+                &IntRect,
+                &mut EnemySprite,
+                &EnemyHittable,
+            )>()
+            .iter()
+        {
+            spr.muzzle_flash = None;
+            if hittable.is_stunned() {
+                continue;
+            }
+            let def = resources.enemy_defs.get(*typ);
+            let centre = rect.centre();
+            let seen = player.filter(|p| {
+                p.distance(centre) < def.sight_range
+                    && clear_line(world, &resources.body_index, centre, *p)
+            });
+            let mut anim = EnemyAnim::Walk;
+            match beh.state {
+                FlyState::Patrol => {
+                    // bob up and down while going back and forth, or on the spot
+                    let bob = (beh.state_timer as f32 * std::f32::consts::TAU / 64.0).sin() * 4.0;
+                    let offset = centre.x - beh.home.x;
+                    if def.patrol_distance > 0.0
+                        // it slows down near the end, so turn a little early
+                        && offset * beh.facing as f32 >= def.patrol_distance - 4.0
+                    {
+                        beh.facing = -beh.facing;
+                    }
+                    let target = beh.home + vec2(beh.facing as f32 * def.patrol_distance, bob);
+                    fly_towards(world, resources, actor, rect, target, def.speed);
+                    if seen.is_some() {
+                        beh.set_state(FlyState::Chase);
+                    }
+                }
+                FlyState::Chase => match seen {
+                    None => beh.set_state(FlyState::Return),
+                    Some(p) => {
+                        beh.facing = if p.x < centre.x { -1 } else { 1 };
+                        // hang back level with the player to shoot, or get above them to dive
+                        let target = match def.attack {
+                            AttackKind::Dive => p - vec2(0.0, FLY_DIVE_HEIGHT),
+                            _ => p - vec2(beh.facing as f32 * FLY_SHOOT_DISTANCE, 0.0),
+                        };
+                        fly_towards(world, resources, actor, rect, target, def.speed);
+                        let ready = beh.attack_timer == 0
+                            && target.distance(centre) < 24.0
+                            && with_prob(def.attack_chance);
+                        match def.attack {
+                            AttackKind::Dive if ready => {
+                                beh.dive_dir = (p - centre).normalize_or_zero();
+                                beh.set_state(FlyState::Dive);
+                            }
+                            AttackKind::Laser | AttackKind::Fireball if ready => {
+                                beh.set_state(FlyState::Attack);
+                            }
+                            _ => (),
+                        }
+                    }
+                },
+                FlyState::Attack => {
+                    anim = EnemyAnim::Attack;
+                    let freq = def.shot_interval;
+                    let mf = beh.state_timer % freq;
+                    spr.muzzle_flash = if mf > 0 && mf < 5 { Some(mf) } else { None };
+                    if beh.state_timer % freq == 1 {
+                        actor.vx -= beh.facing as f32 * def.recoil;
+                        let new_x = rect.x + 7 + beh.facing as i32 * 6;
+                        match def.attack {
+                            AttackKind::Laser => {
+                                let rect = IntRect::new(new_x, rect.y + 8, 8, 5);
+                                make_enemy_laser(buffer, rect, beh.facing as f32 * def.shot_speed);
+                            }
+                            AttackKind::Fireball => {
+                                // flyers can aim up and down as well
+                                let aim = player.map_or(vec2(beh.facing as f32, 0.0), |p| {
+                                    (p - centre).normalize_or_zero()
+                                });
+                                let rect = IntRect::new(new_x - 6, rect.y + 4, 12, 12);
+                                let v = aim * def.shot_speed;
+                                make_enemy_fireball(buffer, rect, v.x, v.y, true);
+                            }
+                            _ => (),
+                        }
+                    }
+                    if beh.state_timer >= def.attack_length {
+                        beh.attack_timer = def.attack_cooldown;
+                        beh.set_state(FlyState::Chase);
+                    }
+                }
+                FlyState::Dive => {
+                    anim = EnemyAnim::Attack;
+                    let ahead = IntRect::new(
+                        rect.x + (beh.dive_dir.x * FLY_LOOKAHEAD).round() as i32,
+                        rect.y + (beh.dive_dir.y * FLY_LOOKAHEAD).round() as i32,
+                        rect.w,
+                        rect.h,
+                    );
+                    // pull out before hitting the ground
+                    if beh.state_timer >= def.attack_length
+                        || collide_any(world, &resources.body_index, &ahead)
+                    {
+                        beh.attack_timer = def.attack_cooldown;
+                        beh.set_state(FlyState::Chase);
+                    } else {
+                        actor.vx += beh.dive_dir.x * def.shot_speed;
+                        actor.vy += beh.dive_dir.y * def.shot_speed;
+                    }
+                }
+                FlyState::Return => {
+                    beh.facing = if beh.home.x < centre.x { -1 } else { 1 };
+                    fly_towards(world, resources, actor, rect, beh.home, def.speed);
+                    if seen.is_some() {
+                        beh.set_state(FlyState::Chase);
+                    } else if beh.home.distance(centre) < 4.0 {
+                        beh.set_state(FlyState::Patrol);
+                    }
+                }
+            }
+            spr.set_anim(anim);
+
+            if beh.attack_timer > 0 {
+                beh.attack_timer -= 1;
+            }
+            // wrapping keeps the bobbing smooth, as it repeats every 64 frames
+            beh.state_timer = beh.state_timer.wrapping_add(1);
+            spr.flipped = beh.facing > 0;
+        }
+    }
+}
+
+// how far either side of straight out a turret can aim, in radians
+const TURRET_ARC: f32 = 1.3;
+const TURRET_TURN_SPEED: f32 = 0.06;
+// how close to on target it has to be to fire
+const TURRET_FIRE_ANGLE: f32 = 0.15;
+pub const TURRET_BARREL: f32 = 10.0;
+// lasers only go sideways, so laser turrets wait until they're aiming about level
+const TURRET_LASER_SLOPE: f32 = 0.3;
+
+pub struct TurretBehaviour {
+    direction: TurretDirection,
+    // relative to straight out from the wall
+    aim: f32,
+    rate: u8,
+    timer: u8,
+}
+
+impl TurretBehaviour {
+    pub fn new(direction: TurretDirection, rate: u8) -> Self {
+        Self {
+            direction,
+            aim: 0.0,
+            rate,
+            timer: rate,
+        }
+    }
+
+    // which way the barrel is pointing
+    pub fn aim_vector(&self) -> Vec2 {
+        let out = self.direction.vector();
+        let a = out.y.atan2(out.x) + self.aim;
+        vec2(a.cos(), a.sin())
+    }
+
+    pub fn update(world: &World, resources: &SceneResources, buffer: &mut CommandBuffer) {
+        let player = world
+            .get::<&IntRect>(resources.player_id)
+            .ok()
+            .map(|r| r.centre());
+        for (_, (typ, turret, rect, hittable)) in world
+            .query::<(&EnemyType, &mut TurretBehaviour, &IntRect, &EnemyHittable)>()
+            .iter()
+        {
+            turret.timer = turret.timer.saturating_sub(1);
+            if hittable.is_stunned() {
+                continue;
+            }
+            let def = resources.enemy_defs.get(*typ);
+            let centre = rect.centre();
+            let p = match player.filter(|p| {
+                p.distance(centre) < def.sight_range
+                    && clear_line(world, &resources.body_index, centre, *p)
+            }) {
+                Some(p) => p,
+                None => continue,
+            };
+            let out = turret.direction.vector();
+            let to = p - centre;
+            let target =
+                wrap_angle(to.y.atan2(to.x) - out.y.atan2(out.x)).clamp(-TURRET_ARC, TURRET_ARC);
+            turret.aim += (target - turret.aim).clamp(-TURRET_TURN_SPEED, TURRET_TURN_SPEED);
+            if turret.timer > 0 || (target - turret.aim).abs() > TURRET_FIRE_ANGLE {
+                continue;
+            }
+            let dir = turret.aim_vector();
+            let muzzle = centre + dir * TURRET_BARREL;
+            let (mx, my) = (muzzle.x as i32, muzzle.y as i32);
+            match def.attack {
+                AttackKind::Laser if dir.y.abs() < TURRET_LASER_SLOPE => {
+                    let rect = IntRect::new(mx - 4, my - 2, 8, 5);
+                    make_enemy_laser(buffer, rect, dir.x.signum() * def.shot_speed);
+                }
+                AttackKind::Fireball => {
+                    let rect = IntRect::new(mx - 6, my - 6, 12, 12);
+                    let v = dir * def.shot_speed;
+                    make_enemy_fireball(buffer, rect, v.x, v.y, true);
+                }
+                _ => continue,
+            }
+            turret.timer = turret.rate;
+        }
+    }
+}
+
+// in the range -PI to PI
+fn wrap_angle(a: f32) -> f32 {
+    (a + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU) - std::f32::consts::PI
+}
+
+// marks enemies made by a spawner, which aren't counted in the level's kills
+pub struct Spawned {
+    spawner: Entity,
+}
+
+pub struct SpawnerBehaviour {
+    spawn: Option<EnemyType>,
+    rate: u8,
+    max: u8,
+    timer: u8,
+}
+
+impl SpawnerBehaviour {
+    pub fn new(spawn: Option<EnemyType>, rate: u8, max: u8) -> Self {
+        Self {
+            spawn,
+            rate,
+            max,
+            timer: rate,
+        }
+    }
+
+    // from 0 just after making an enemy up to 1 when it's ready to make the next
+    pub fn charge(&self) -> f32 {
+        1.0 - self.timer as f32 / self.rate as f32
+    }
+
+    pub fn update(world: &World, resources: &SceneResources, buffer: &mut CommandBuffer) {
+        let mut alive: HashMap<Entity, u8> = HashMap::new();
+        for (_, (spawned, health)) in world.query::<(&Spawned, &Health)>().iter() {
+            if health.is_alive() {
+                *alive.entry(spawned.spawner).or_insert(0) += 1;
+            }
+        }
+        let player = world
+            .get::<&IntRect>(resources.player_id)
+            .ok()
+            .map(|r| r.centre());
+        for (id, (typ, spawner, rect, hittable)) in world
+            .query::<(&EnemyType, &mut SpawnerBehaviour, &IntRect, &EnemyHittable)>()
+            .iter()
+        {
+            let spawn = match spawner.spawn {
+                Some(spawn) => spawn,
+                None => continue,
+            };
+            let def = resources.enemy_defs.get(*typ);
+            // only bother while the player is about
+            if hittable.is_stunned()
+                || !player.map_or(false, |p| p.distance(rect.centre()) < def.sight_range)
+            {
+                continue;
+            }
+            spawner.timer = spawner.timer.saturating_sub(1);
+            if spawner.timer > 0 || alive.get(&id).copied().unwrap_or(0) >= spawner.max {
+                continue;
+            }
+            // out of the bottom middle, as long as there's room
+            let (x, y) = (rect.centre().x as i32, rect.y + rect.h);
+            let spawn_def = resources.enemy_defs.get(spawn);
+            let spawn_rect = IntRect::new(
+                x - spawn_def.width / 2,
+                y - spawn_def.height,
+                spawn_def.width,
+                spawn_def.height,
+            );
+            if collide_any(world, &resources.body_index, &spawn_rect) {
+                continue;
+            }
+            let settings = EnemySettings::new(&resources.enemy_defs, spawn);
+            let mut b = enemy_builder(&resources.enemy_defs, spawn, x, y, &settings);
+            b.add(Spawned { spawner: id });
+            buffer.spawn(b.build());
+            let (cx, cy) = spawn_rect.centre_int();
+            buffer.spawn((ZapFlash::new_from_centre(cx, cy),));
+            spawner.timer = spawner.rate;
+        }
+    }
+}
+
+// whether a straight line between two points misses every blocker tile
+fn clear_line(world: &World, body_index: &SpatialIndex, a: Vec2, b: Vec2) -> bool {
+    let steps = (a.distance(b) / 4.0).ceil().max(1.0) as i32;
+    (0..=steps).all(|i| {
+        let p = a.lerp(b, i as f32 / steps as f32);
+        !collide_any(
+            world,
+            body_index,
+            &IntRect::new(p.x as i32, p.y as i32, 1, 1),
+        )
+    })
+}
+
+// heads for the target, trying directions either side of it when the way is blocked
+fn fly_towards(
+    world: &World,
+    resources: &SceneResources,
+    actor: &mut Actor,
+    rect: &IntRect,
+    target: Vec2,
+    speed: f32,
+) {
+    let to = target - rect.centre();
+    let dist = to.length();
+    if dist < 1.0 {
+        return;
+    }
+    let dir = to / dist;
+    // ease off when close so as not to overshoot
+    let speed = speed * (dist / 16.0).min(1.0);
+    for angle in FLY_STEER_ANGLES.iter() {
+        let (sin, cos) = angle.sin_cos();
+        let d = vec2(dir.x * cos - dir.y * sin, dir.x * sin + dir.y * cos);
+        let probe = IntRect::new(
+            rect.x + (d.x * FLY_LOOKAHEAD).round() as i32,
+            rect.y + (d.y * FLY_LOOKAHEAD).round() as i32,
+            rect.w,
+            rect.h,
+        );
+        if !collide_any(world, &resources.body_index, &probe) {
+            actor.vx += d.x * speed;
+            actor.vy += d.y * speed;
+            return;
+        }
+    }
+}
+
+fn patrol_should_stop(world: &World, resources: &SceneResources, rect: &IntRect, vx: f32) -> bool {
     let d = vx.abs().ceil() as i32;
     let (wall_rect_x, floor_rect_x) = match vx.total_cmp(&0.0) {
         Ordering::Equal => return false,
@@ -344,7 +846,7 @@ fn parrot_should_stop(world: &World, resources: &SceneResources, rect: &IntRect,
 }
 
 // detect whether the enemy's rear foot is sliding off a cliff as a result of firing recoil
-fn parrot_off_edge(world: &World, resources: &SceneResources, rect: &IntRect, facing: i8) -> bool {
+fn patrol_off_edge(world: &World, resources: &SceneResources, rect: &IntRect, facing: i8) -> bool {
     let x = if facing > 0 {
         rect.x
     } else {
@@ -359,41 +861,61 @@ fn parrot_off_edge(world: &World, resources: &SceneResources, rect: &IntRect, fa
 
 pub fn update_enemies(resources: &mut SceneResources, buffer: &mut CommandBuffer) {
     let world = resources.world_ref.lock().unwrap();
-    DogBehaviour::update(&world, resources);
-    ParrotBehaviour::update(&world, resources, buffer);
+    HopBehaviour::update(&world, resources);
+    PatrolBehaviour::update(&world, resources, buffer);
+    FlyBehaviour::update(&world, resources, buffer);
+    TurretBehaviour::update(&world, resources, buffer);
+    SpawnerBehaviour::update(&world, resources, buffer);
 
-    for (id, (actor, rect, kind, hittable)) in world
-        .query::<(&Actor, &IntRect, &EnemyKind, &mut EnemyHittable)>()
-        .iter()
-    {
+    for (id, hittable) in world.query::<&mut EnemyHittable>().iter() {
         hittable.was_hit = false;
-        if hittable.hp == 0 || actor.crushed {
-            match kind {
-                EnemyKind::Dog | EnemyKind::JumpyDog => {
-                    resources.messages.add("Destroyed a hound.".to_owned())
+        // turrets and spawners can be stunned but don't budge
+        if let Some(k) = hittable.knockback.take() {
+            if let Ok(mut q) = world.query_one::<(&mut Actor, &IntRect)>(id) {
+                if let Some((actor, rect)) = q.get() {
+                    k.apply(actor, rect);
                 }
-                EnemyKind::SpiderParrot(ParrotKind::Laser) => resources
-                    .messages
-                    .add("Destroyed a red scuttler.".to_owned()),
-                EnemyKind::SpiderParrot(ParrotKind::Cannon) => resources
-                    .messages
-                    .add("Destroyed a green scuttler.".to_owned()),
             }
-            buffer.despawn(id);
-            let (ex, ey) = rect.centre_int();
-            create_explosion(buffer, ex, ey);
-            resources.stats.kills += 1
         }
+        hittable.stun_timer = hittable.stun_timer.saturating_sub(1);
     }
 
-    if let Ok(mut q) = world.query_one::<(&mut Controller, &IntRect)>(resources.player_id) {
-        if let Some((c, p_rect)) = q.get() {
-            for (_, (_, rect)) in world.query::<(&EnemyContactDamage, &IntRect)>().iter() {
+    if let Ok(mut q) =
+        world.query_one::<(&mut Controller, &mut Health, &IntRect)>(resources.player_id)
+    {
+        // dashing carries the player straight through enemies
+        if let Some((c, health, p_rect)) = q.get().filter(|(c, _, _)| !c.is_dashing()) {
+            for (_, (contact, rect)) in world.query::<(&EnemyContactDamage, &IntRect)>().iter() {
                 if rect.intersects(p_rect) {
-                    c.hurt();
+                    c.hurt(
+                        health,
+                        Damage::new(contact.amount, DamageType::Impact),
+                        Impulse::new(rect.centre(), CONTACT_KNOCKBACK),
+                    );
                     break; // player will get damage invulnerability so might as well stop
                 }
             }
         }
     };
 }
+
+pub fn handle_enemy_deaths(resources: &mut SceneResources, buffer: &mut CommandBuffer) {
+    let world = resources.world_ref.lock().unwrap();
+    for DeathEvent {
+        id, victim, x, y, ..
+    } in &resources.deaths
+    {
+        let typ = match victim {
+            Victim::Enemy(typ) => typ,
+            _ => continue,
+        };
+        let name = &resources.enemy_defs.get(*typ).name;
+        resources.messages.add(format!("Destroyed a {}.", name));
+        buffer.despawn(*id);
+        create_explosion(buffer, *x, *y);
+        // spawned enemies weren't counted in max_kills, only their spawner
+        if !world.satisfies::<&Spawned>(*id).unwrap_or(false) {
+            resources.stats.kills += 1
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use crate::datafile::{self, set, set_nonzero, set_value, Line};
+use crate::health::DamageType;
+use enum_map::{enum_map, EnumMap};
+use macroquad::prelude::{load_string, load_texture, Texture2D};
+use std::collections::HashMap;
+
+const ENEMIES_FILE: &str = "enemies.txt";
+
+// an index into the enemy definitions
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EnemyType(usize);
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum BehaviourKind {
+    // bounds around at random, mostly towards the player
+    Hop,
+    // walks back and forth without falling off ledges, stopping to shoot
+    Patrol,
+    // ignores gravity, patrolling or hovering until it sees the player and goes after them
+    Fly,
+    // fixed to a wall or ceiling, turning to aim at the player and firing every so often
+    Turret,
+    // stays put, making more enemies while the player is nearby
+    Spawner,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum AttackKind {
+    None,
+    Laser,
+    // splits into smaller fireballs when it hits something
+    Fireball,
+    // flyers only, swooping straight at the player
+    Dive,
+}
+
+#[derive(Clone)]
+pub struct EnemyDef {
+    pub id: String,
+    pub name: String,
+    pub width: i32,
+    pub height: i32,
+    pub hp: u16,
+    pub resistances: EnumMap<DamageType, f32>,
+    pub contact_damage: u16,
+    pub sprite: String,
+    pub frame_width: f32,
+    pub frame_height: f32,
+    // frames of the animation in the sprite sheet, top to bottom
+    pub idle_frames: Vec<u8>,
+    pub walk_frames: Vec<u8>,
+    pub attack_frames: Vec<u8>,
+    // game frames per animation frame
+    pub frame_time: u8,
+    pub behaviour: BehaviourKind,
+    pub speed: f32,
+    pub jump_chance: f32,
+    pub jump_speed: f32,
+    pub attack: AttackKind,
+    pub attack_chance: f32,
+    // only shoots if the player is level with it and not behind a wall
+    pub line_of_sight: bool,
+    pub shot_interval: u8,
+    pub attack_length: u8,
+    pub attack_cooldown: u8,
+    pub shot_speed: f32,
+    pub recoil: f32,
+    // for flyers, how far they can see the player from
+    pub sight_range: f32,
+    // for flyers, how far either side of where they started they patrol, 0 to hover
+    pub patrol_distance: f32,
+    // for turrets, frames between shots, and for spawners, frames between enemies
+    pub rate: u8,
+    // for spawners, the id of the enemy to make and how many of them can be about at once
+    pub spawn: String,
+    pub spawn_max: u8,
+    // filled in from the sprite once parsing is done
+    pub texture: Option<Texture2D>,
+}
+
+impl EnemyDef {
+    fn new(id: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            name: id.replace('_', " "),
+            width: 24,
+            height: 16,
+            hp: 3,
+            resistances: enum_map! { _ => 1.0 },
+            contact_damage: 1,
+            sprite: String::new(),
+            frame_width: 0.0,
+            frame_height: 0.0,
+            idle_frames: vec![0],
+            walk_frames: vec![0],
+            attack_frames: vec![0],
+            frame_time: 1,
+            behaviour: BehaviourKind::Hop,
+            speed: 5.0,
+            jump_chance: 0.0,
+            jump_speed: 0.0,
+            attack: AttackKind::None,
+            attack_chance: 1.0,
+            line_of_sight: false,
+            shot_interval: 6,
+            attack_length: 24,
+            attack_cooldown: 30,
+            shot_speed: 4.0,
+            recoil: 10.0,
+            sight_range: 160.0,
+            patrol_distance: 0.0,
+            rate: 60,
+            spawn: String::new(),
+            spawn_max: 3,
+            texture: None,
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> bool {
+        match key {
+            "name" => {
+                self.name = value.to_owned();
+                true
+            }
+            "sprite" => {
+                self.sprite = value.to_owned();
+                true
+            }
+            "spawn" => {
+                self.spawn = value.to_owned();
+                true
+            }
+            "behaviour" => match value {
+                "hop" => set_value(&mut self.behaviour, BehaviourKind::Hop),
+                "patrol" => set_value(&mut self.behaviour, BehaviourKind::Patrol),
+                "fly" => set_value(&mut self.behaviour, BehaviourKind::Fly),
+                "turret" => set_value(&mut self.behaviour, BehaviourKind::Turret),
+                "spawner" => set_value(&mut self.behaviour, BehaviourKind::Spawner),
+                _ => false,
+            },
+            "attack" => match value {
+                "none" => set_value(&mut self.attack, AttackKind::None),
+                "laser" => set_value(&mut self.attack, AttackKind::Laser),
+                "fireball" => set_value(&mut self.attack, AttackKind::Fireball),
+                "dive" => set_value(&mut self.attack, AttackKind::Dive),
+                _ => false,
+            },
+            // e.g. "resist fire 1.5"
+            "resist" => match value.split_once(char::is_whitespace) {
+                Some((typ, amount)) => match DamageType::from_name(typ) {
+                    Some(typ) => set(&mut self.resistances[typ], amount.trim()),
+                    None => false,
+                },
+                None => false,
+            },
+            "idle_frames" => set_frames(&mut self.idle_frames, value),
+            "walk_frames" => set_frames(&mut self.walk_frames, value),
+            "attack_frames" => set_frames(&mut self.attack_frames, value),
+            "width" => set(&mut self.width, value),
+            "height" => set(&mut self.height, value),
+            "hp" => set(&mut self.hp, value),
+            "contact_damage" => set(&mut self.contact_damage, value),
+            "frame_width" => set(&mut self.frame_width, value),
+            "frame_height" => set(&mut self.frame_height, value),
+            "frame_time" => set_nonzero(&mut self.frame_time, value),
+            "speed" => set(&mut self.speed, value),
+            "jump_chance" => set(&mut self.jump_chance, value),
+            "jump_speed" => set(&mut self.jump_speed, value),
+            "attack_chance" => set(&mut self.attack_chance, value),
+            "line_of_sight" => set(&mut self.line_of_sight, value),
+            "shot_interval" => set_nonzero(&mut self.shot_interval, value),
+            "attack_length" => set(&mut self.attack_length, value),
+            "attack_cooldown" => set(&mut self.attack_cooldown, value),
+            "shot_speed" => set(&mut self.shot_speed, value),
+            "recoil" => set(&mut self.recoil, value),
+            "sight_range" => set(&mut self.sight_range, value),
+            "patrol_distance" => set(&mut self.patrol_distance, value),
+            "rate" => set_nonzero(&mut self.rate, value),
+            "spawn_max" => set(&mut self.spawn_max, value),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EnemyDefs {
+    defs: Vec<EnemyDef>,
+}
+
+impl EnemyDefs {
+    // an [id] line starts each enemy, followed by "name value" pairs
+    pub fn parse(text: &str) -> Self {
+        let mut defs: Vec<EnemyDef> = Vec::new();
+        for (line, parsed) in datafile::lines(text) {
+            let (key, value) = match parsed {
+                Line::Section(id) => {
+                    defs.push(EnemyDef::new(id));
+                    continue;
+                }
+                Line::Setting(key, value) => (key, value),
+            };
+            let def = match defs.last_mut() {
+                Some(def) => def,
+                None => {
+                    println!("{}: setting '{}' before any enemy", ENEMIES_FILE, line);
+                    continue;
+                }
+            };
+            // every enemy setting needs a value
+            if value.is_empty() || !def.set(key, value) {
+                println!("{}: bad setting '{}' for {}", ENEMIES_FILE, line, def.id);
+            }
+        }
+        // spawners making spawners would never stop
+        let spawners: Vec<String> = defs
+            .iter()
+            .filter(|d| d.behaviour == BehaviourKind::Spawner)
+            .map(|d| d.id.clone())
+            .collect();
+        for def in &mut defs {
+            if spawners.contains(&def.spawn) {
+                println!("{}: {} can't spawn {}", ENEMIES_FILE, def.id, def.spawn);
+                def.spawn.clear();
+            }
+            // frames are the size of the hitbox unless given
+            if def.frame_width <= 0.0 {
+                def.frame_width = def.width as f32;
+            }
+            if def.frame_height <= 0.0 {
+                def.frame_height = def.height as f32;
+            }
+        }
+        Self { defs }
+    }
+
+    pub fn find(&self, id: &str) -> Option<EnemyType> {
+        self.defs.iter().position(|d| d.id == id).map(EnemyType)
+    }
+
+    pub fn get(&self, typ: EnemyType) -> &EnemyDef {
+        &self.defs[typ.0]
+    }
+}
+
+// a list of sprite sheet rows separated by spaces
+fn set_frames(field: &mut Vec<u8>, value: &str) -> bool {
+    let frames: Result<Vec<u8>, _> = value.split_whitespace().map(str::parse).collect();
+    match frames {
+        Ok(frames) if !frames.is_empty() => set_value(field, frames),
+        _ => false,
+    }
+}
+
+pub async fn load_enemy_defs() -> Result<EnemyDefs, String> {
+    let text = load_string(ENEMIES_FILE)
+        .await
+        .map_err(|_| format!("couldn't load {}", ENEMIES_FILE))?;
+    let mut defs = EnemyDefs::parse(&text);
+    // several kinds of enemy can share a sprite sheet
+    let mut textures: HashMap<String, Texture2D> = HashMap::new();
+    for def in &mut defs.defs {
+        // turrets and spawners are drawn without one
+        if def.sprite.is_empty() {
+            continue;
+        }
+        let texture = match textures.get(&def.sprite) {
+            Some(t) => *t,
+            None => {
+                let t = load_texture(&def.sprite)
+                    .await
+                    .map_err(|_| format!("{}: couldn't load {}", def.id, def.sprite))?;
+                textures.insert(def.sprite.clone(), t);
+                t
+            }
+        };
+        def.texture = Some(texture);
+    }
+    Ok(defs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHIPPED: &str = include_str!("../assets/enemies.txt");
+
+    fn shipped(id: &str) -> EnemyDef {
+        let defs = EnemyDefs::parse(SHIPPED);
+        let typ = defs
+            .find(id)
+            .unwrap_or_else(|| panic!("no {} in the data", id));
+        defs.get(typ).clone()
+    }
+
+    // every damage type hurts by the full amount, as everything did before resistances
+    fn neutral(def: &EnemyDef) -> bool {
+        def.resistances.values().all(|&r| r == 1.0)
+    }
+
+    // these values were hard coded before enemies.txt existed
+    #[test]
+    fn hounds_behave_as_before() {
+        for &(id, jump_chance, jump_speed) in &[("enemy", 0.2, 8.0), ("walker_enemy", 0.45, 6.0)] {
+            let def = shipped(id);
+            assert_eq!((def.width, def.height, def.hp), (24, 16, 3), "{}", id);
+            assert!(def.behaviour == BehaviourKind::Hop, "{}", id);
+            assert_eq!(def.speed, 5.0, "{}", id);
+            assert_eq!(def.jump_chance, jump_chance, "{}", id);
+            assert_eq!(def.jump_speed, jump_speed, "{}", id);
+            assert_eq!(def.contact_damage, 1, "{}", id);
+            assert!(neutral(&def), "{}", id);
+        }
+    }
+
+    // as were these
+    #[test]
+    fn scuttlers_behave_as_before() {
+        let laser = shipped("parrot_enemy");
+        assert_eq!((laser.width, laser.height, laser.hp), (24, 24, 7));
+        assert!(laser.behaviour == BehaviourKind::Patrol);
+        assert!(laser.attack == AttackKind::Laser);
+        assert!(neutral(&laser));
+        assert!(!laser.line_of_sight);
+        assert_eq!(laser.attack_chance, 0.85);
+        assert_eq!(
+            (
+                laser.shot_interval,
+                laser.attack_length,
+                laser.attack_cooldown
+            ),
+            (6, 24, 30)
+        );
+        assert_eq!((laser.shot_speed, laser.recoil), (4.0, 10.0));
+
+        let cannon = shipped("cannon_parrot_enemy");
+        assert_eq!((cannon.width, cannon.height, cannon.hp), (24, 24, 7));
+        assert!(cannon.behaviour == BehaviourKind::Patrol);
+        assert!(cannon.attack == AttackKind::Fireball);
+        assert!(neutral(&cannon));
+        assert!(cannon.line_of_sight);
+        assert_eq!(cannon.attack_chance, 1.0);
+        assert_eq!(
+            (
+                cannon.shot_interval,
+                cannon.attack_length,
+                cannon.attack_cooldown
+            ),
+            (24, 12, 120)
+        );
+        assert_eq!((cannon.shot_speed, cannon.recoil), (2.0, 10.0));
+    }
+
+    #[test]
+    fn bad_settings_are_skipped() {
+        let defs = EnemyDefs::parse(
+            "hp 4\n[blob]\nhp lots\nwidth 10\nframe_time 0\nbehaviour wobble\nname\n",
+        );
+        let def = defs.get(defs.find("blob").unwrap());
+        assert_eq!(def.hp, 3);
+        assert_eq!(def.width, 10);
+        assert_eq!(def.frame_time, 1);
+        assert!(def.behaviour == BehaviourKind::Hop);
+        assert_eq!(def.name, "blob");
+        // frames default to the size of the hitbox
+        assert_eq!((def.frame_width, def.frame_height), (10.0, 16.0));
+    }
+
+    #[test]
+    fn spawners_cannot_spawn_spawners() {
+        let defs = EnemyDefs::parse(
+            "[nest]\nbehaviour spawner\nspawn nest\n[kennel]\nbehaviour spawner\nspawn hound\n[hound]\n",
+        );
+        assert_eq!(defs.get(defs.find("nest").unwrap()).spawn, "");
+        assert_eq!(defs.get(defs.find("kennel").unwrap()).spawn, "hound");
+    }
+}
@@ -0,0 +1,221 @@
+use crate::enemy_defs::EnemyType;
+use crate::physics::{Actor, IntRect};
+use crate::resources::SceneResources;
+use enum_map::{enum_map, Enum, EnumMap};
+use hecs::Entity;
+
+const OVERHEAL_DECAY_FRAMES: u16 = 90;
+
+#[derive(Enum, Copy, Clone, PartialEq, Eq)]
+pub enum DamageType {
+    Laser,
+    Explosive,
+    Fire,
+    // squashed by something solid
+    Crush,
+    Melee,
+    // blunt hits, like being run into by an enemy
+    Impact,
+}
+
+impl DamageType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "laser" => Some(Self::Laser),
+            "explosive" => Some(Self::Explosive),
+            "fire" => Some(Self::Fire),
+            "crush" => Some(Self::Crush),
+            "melee" => Some(Self::Melee),
+            "impact" => Some(Self::Impact),
+            _ => None,
+        }
+    }
+}
+
+// attached to projectiles to say how much they hurt
+#[derive(Copy, Clone)]
+pub struct Damage {
+    pub amount: u16,
+    pub typ: DamageType,
+}
+
+impl Damage {
+    pub fn new(amount: u16, typ: DamageType) -> Self {
+        Self { amount, typ }
+    }
+}
+
+pub struct Health {
+    pub hp: u16,
+    pub max_hp: u16,
+    // soaks up damage before it reaches hp
+    pub armor: u16,
+    // multiplier applied to each type of incoming damage
+    pub resistances: EnumMap<DamageType, f32>,
+    pub invulnerable: bool,
+    // counts up while overhealed, hp above the max drains away one point at a time
+    decay_timer: u16,
+    last_damage: DamageType,
+    dead: bool,
+}
+
+impl Health {
+    pub fn new(max_hp: u16) -> Self {
+        Self {
+            hp: max_hp,
+            max_hp,
+            armor: 0,
+            resistances: enum_map! { _ => 1.0 },
+            invulnerable: false,
+            decay_timer: 0,
+            last_damage: DamageType::Laser,
+            dead: false,
+        }
+    }
+
+    pub fn with_resistances(mut self, resistances: EnumMap<DamageType, f32>) -> Self {
+        self.resistances = resistances;
+        self
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.hp > 0
+    }
+
+    // returns how many hit points were actually lost
+    // each hit is scaled by the resistance and rounded to the nearest point on its own,
+    // but only a resistance of 0 can take a hit all the way down to nothing
+    pub fn damage(&mut self, dmg: Damage) -> u16 {
+        if self.invulnerable || self.hp == 0 {
+            return 0;
+        }
+        self.last_damage = dmg.typ;
+        let mut amount = scale_damage(dmg.amount, self.resistances[dmg.typ]);
+        let absorbed = amount.min(self.armor);
+        self.armor -= absorbed;
+        amount -= absorbed;
+        let lost = amount.min(self.hp);
+        self.hp -= lost;
+        lost
+    }
+
+    pub fn kill(&mut self, typ: DamageType) {
+        if !self.invulnerable {
+            self.last_damage = typ;
+            self.hp = 0;
+        }
+    }
+
+    pub fn can_heal(&self) -> bool {
+        self.hp < self.max_hp
+    }
+
+    pub fn heal(&mut self, amount: u16) {
+        self.hp = (self.hp + amount).min(self.max_hp);
+    }
+
+    // heal past the usual maximum, up to double it
+    pub fn overheal(&mut self, amount: u16) {
+        self.hp = (self.hp.max(self.max_hp) + amount).min(self.max_hp * 2);
+        self.decay_timer = 0;
+    }
+
+    fn decay(&mut self) {
+        if self.hp > self.max_hp {
+            self.decay_timer += 1;
+            if self.decay_timer >= OVERHEAL_DECAY_FRAMES {
+                self.hp -= 1;
+                self.decay_timer = 0;
+            }
+        } else {
+            self.decay_timer = 0;
+        }
+    }
+}
+
+fn scale_damage(amount: u16, resistance: f32) -> u16 {
+    if amount == 0 || resistance <= 0.0 {
+        return 0;
+    }
+    (amount as f32 * resistance).round().max(1.0) as u16
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Victim {
+    Player,
+    Enemy(EnemyType),
+}
+
+pub struct DeathEvent {
+    pub id: Entity,
+    pub victim: Victim,
+    pub x: i32,
+    pub y: i32,
+    pub cause: DamageType,
+}
+
+// turns crushed actors and anything that has run out of health into death events,
+// which other systems pick up from resources.deaths for the rest of the frame
+pub fn update_health(resources: &mut SceneResources) {
+    let world = resources.world_ref.lock().unwrap();
+    for (id, (health, rect)) in world.query::<(&mut Health, &IntRect)>().iter() {
+        if health.dead {
+            continue;
+        }
+        health.decay();
+        if world.get::<&Actor>(id).map_or(false, |a| a.crushed) {
+            health.kill(DamageType::Crush);
+        }
+        if health.hp > 0 {
+            continue;
+        }
+        let victim = if id == resources.player_id {
+            Victim::Player
+        } else if let Ok(typ) = world.get::<&EnemyType>(id) {
+            Victim::Enemy(*typ)
+        } else {
+            // only the player and enemies have health
+            continue;
+        };
+        health.dead = true;
+        let (x, y) = rect.centre_int();
+        resources.deaths.push(DeathEvent {
+            id,
+            victim,
+            x,
+            y,
+            cause: health.last_damage,
+        });
+    }
+}
+
+// let scripts react to deaths too
+pub fn death_triggers(resources: &mut SceneResources) {
+    for d in &resources.deaths {
+        match d.victim {
+            Victim::Player => resources.triggers.insert("player_death".to_owned()),
+            Victim::Enemy(_) => resources.triggers.insert("enemy_death".to_owned()),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_are_rounded_one_at_a_time() {
+        let mut health = Health::new(20);
+        health.resistances[DamageType::Fire] = 1.5;
+        health.resistances[DamageType::Laser] = 0.5;
+        // 1.5 rounds up to 2, and nothing is left over to top up the next hit
+        assert_eq!(health.damage(Damage::new(1, DamageType::Fire)), 2);
+        assert_eq!(health.damage(Damage::new(3, DamageType::Laser)), 2);
+        // resisted hits always do at least 1
+        for _ in 0..3 {
+            assert_eq!(health.damage(Damage::new(1, DamageType::Laser)), 1);
+        }
+        health.resistances[DamageType::Laser] = 0.0;
+        assert_eq!(health.damage(Damage::new(5, DamageType::Laser)), 0);
+    }
+}
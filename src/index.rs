@@ -1,32 +1,34 @@
 use crate::physics::IntRect;
-use hecs::Entity;
+use hecs::{Entity, World};
 use smallset::SmallSet;
 use std::collections::HashMap;
 
-const BUCKET_SIZE: i32 = 128;
+pub const DEFAULT_CELL_SIZE: i32 = 128;
 
 pub struct SpatialIndex {
+    cell_size: i32,
     buckets: HashMap<(i32, i32), SmallSet<[Entity; 8]>>,
 }
 
-fn get_bounds(rect: &IntRect) -> (i32, i32, i32, i32) {
-    (
-        rect.x.div_euclid(BUCKET_SIZE),
-        (rect.x + rect.w - 1).div_euclid(BUCKET_SIZE),
-        rect.y.div_euclid(BUCKET_SIZE),
-        (rect.y + rect.h - 1).div_euclid(BUCKET_SIZE),
-    )
-}
-
 impl SpatialIndex {
-    pub fn new() -> Self {
+    pub fn new(cell_size: i32) -> Self {
         Self {
+            cell_size: cell_size.max(1),
             buckets: HashMap::new(),
         }
     }
 
+    fn get_bounds(&self, rect: &IntRect) -> (i32, i32, i32, i32) {
+        (
+            rect.x.div_euclid(self.cell_size),
+            (rect.x + rect.w - 1).div_euclid(self.cell_size),
+            rect.y.div_euclid(self.cell_size),
+            (rect.y + rect.h - 1).div_euclid(self.cell_size),
+        )
+    }
+
     pub fn insert_at(&mut self, entity: Entity, rect: &IntRect) {
-        let (min_kx, max_kx, min_ky, max_ky) = get_bounds(rect);
+        let (min_kx, max_kx, min_ky, max_ky) = self.get_bounds(rect);
         for kx in min_kx..=max_kx {
             for ky in min_ky..=max_ky {
                 let v = self.buckets.entry((kx, ky)).or_insert_with(SmallSet::new);
@@ -36,33 +38,133 @@ impl SpatialIndex {
     }
 
     pub fn remove_at(&mut self, entity: Entity, rect: &IntRect) {
-        let (min_kx, max_kx, min_ky, max_ky) = get_bounds(rect);
+        let (min_kx, max_kx, min_ky, max_ky) = self.get_bounds(rect);
         for kx in min_kx..=max_kx {
             for ky in min_ky..=max_ky {
-                let v = self.buckets.entry((kx, ky)).or_insert_with(SmallSet::new);
-                v.remove(&entity);
+                if let Some(v) = self.buckets.get_mut(&(kx, ky)) {
+                    v.remove(&entity);
+                    // don't leave empty buckets lying around
+                    if v.is_empty() {
+                        self.buckets.remove(&(kx, ky));
+                    }
+                }
             }
         }
     }
 
     pub fn entities(&self, rect: &IntRect) -> SmallSet<[Entity; 8]> {
         let mut result: SmallSet<[Entity; 8]> = SmallSet::new();
-        let (min_kx, max_kx, min_ky, max_ky) = get_bounds(rect);
+        let (min_kx, max_kx, min_ky, max_ky) = self.get_bounds(rect);
         for kx in min_kx..=max_kx {
             for ky in min_ky..=max_ky {
-                if let Some(set) = self.buckets.get(&(kx, ky)) {
-                    for id in set.iter() {
-                        result.insert(*id);
+                self.add_bucket(&mut result, kx, ky);
+            }
+        }
+        result
+    }
+
+    // everything in the bucket containing the point, so still needs an exact test
+    pub fn entities_at_point(&self, x: i32, y: i32) -> SmallSet<[Entity; 8]> {
+        let mut result: SmallSet<[Entity; 8]> = SmallSet::new();
+        self.add_bucket(
+            &mut result,
+            x.div_euclid(self.cell_size),
+            y.div_euclid(self.cell_size),
+        );
+        result
+    }
+
+    // everything in the buckets crossed by a line segment, in the order they were crossed
+    pub fn entities_on_ray(&self, x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<Entity> {
+        let cs = self.cell_size as f32;
+        let (mut kx, mut ky) = ((x0 / cs).floor() as i32, (y0 / cs).floor() as i32);
+        let (end_kx, end_ky) = ((x1 / cs).floor() as i32, (y1 / cs).floor() as i32);
+        let (dx, dy) = (x1 - x0, y1 - y0);
+        let step_x = if dx > 0.0 { 1 } else { -1 };
+        let step_y = if dy > 0.0 { 1 } else { -1 };
+        // distance along the ray (as a fraction of its length) to the next bucket edge
+        let next_edge = |p: f32, k: i32, step: i32, d: f32| {
+            if d == 0.0 {
+                f32::INFINITY
+            } else {
+                let edge = (if step > 0 { k + 1 } else { k }) as f32 * cs;
+                (edge - p) / d
+            }
+        };
+        let mut t_max_x = next_edge(x0, kx, step_x, dx);
+        let mut t_max_y = next_edge(y0, ky, step_y, dy);
+        let t_delta_x = if dx == 0.0 {
+            f32::INFINITY
+        } else {
+            cs / dx.abs()
+        };
+        let t_delta_y = if dy == 0.0 {
+            f32::INFINITY
+        } else {
+            cs / dy.abs()
+        };
+
+        let mut seen: SmallSet<[Entity; 8]> = SmallSet::new();
+        let mut result = Vec::new();
+        loop {
+            if let Some(set) = self.buckets.get(&(kx, ky)) {
+                for id in set.iter() {
+                    if !seen.contains(id) {
+                        seen.insert(*id);
+                        result.push(*id);
                     }
                 }
             }
+            if (kx == end_kx && ky == end_ky) || (t_max_x > 1.0 && t_max_y > 1.0) {
+                break;
+            }
+            if t_max_x < t_max_y {
+                kx += step_x;
+                t_max_x += t_delta_x;
+            } else {
+                ky += step_y;
+                t_max_y += t_delta_y;
+            }
         }
         result
     }
 
+    fn add_bucket(&self, result: &mut SmallSet<[Entity; 8]>, kx: i32, ky: i32) {
+        if let Some(set) = self.buckets.get(&(kx, ky)) {
+            for id in set.iter() {
+                result.insert(*id);
+            }
+        }
+    }
+
+    // the area covered by each non-empty bucket and how many entities it holds
+    pub fn occupancy(&self) -> Vec<(IntRect, usize)> {
+        self.buckets
+            .iter()
+            .map(|((kx, ky), set)| {
+                (
+                    IntRect::new(
+                        kx * self.cell_size,
+                        ky * self.cell_size,
+                        self.cell_size,
+                        self.cell_size,
+                    ),
+                    set.len(),
+                )
+            })
+            .collect()
+    }
+
     #[allow(dead_code)]
-    pub fn debug(&self) {
-        println!("spatial index has {} buckets", self.buckets.len());
+    pub fn debug(&self, name: &str) {
+        let total: usize = self.buckets.values().map(|s| s.len()).sum();
+        println!(
+            "{} has {} buckets of size {} holding {} entries",
+            name,
+            self.buckets.len(),
+            self.cell_size,
+            total
+        );
         let mut counts = HashMap::<usize, usize>::new();
         for s in self.buckets.values() {
             *counts.entry(s.len()).or_insert(0) += 1;
@@ -72,5 +174,154 @@ impl SpatialIndex {
         for key in k {
             println!("{} buckets with {} entries", counts[&key], key);
         }
+        // buckets with more entries than fit inline in the set are a sign the cells are too big
+        let spilled = self.buckets.values().filter(|s| s.len() > 8).count();
+        if spilled > 0 {
+            println!("{} buckets hold more than 8 entries", spilled);
+        }
+    }
+}
+
+// marks the things that get looked up by position: the player, enemies and their shots,
+// pickups, switches and trigger zones. particles and the player's shots are left out
+pub struct Indexed {}
+
+// an index of everything marked Indexed, which is brought up to date by comparing
+// against the rects seen last time
+pub struct DynamicIndex {
+    index: SpatialIndex,
+    rects: HashMap<Entity, IntRect>,
+}
+
+impl DynamicIndex {
+    pub fn new(cell_size: i32) -> Self {
+        Self {
+            index: SpatialIndex::new(cell_size),
+            rects: HashMap::new(),
+        }
+    }
+
+    pub fn update(&mut self, world: &World) {
+        for (id, (rect, _)) in world.query::<(&IntRect, &Indexed)>().iter() {
+            match self.rects.get_mut(&id) {
+                Some(old) if *old == *rect => (),
+                Some(old) => {
+                    self.index.remove_at(id, old);
+                    self.index.insert_at(id, rect);
+                    *old = rect.clone();
+                }
+                None => {
+                    self.index.insert_at(id, rect);
+                    self.rects.insert(id, rect.clone());
+                }
+            }
+        }
+        // forget about anything that has been despawned
+        let index = &mut self.index;
+        self.rects.retain(|id, rect| {
+            let keep = world.contains(*id);
+            if !keep {
+                index.remove_at(*id, rect);
+            }
+            keep
+        });
+    }
+
+    pub fn entities(&self, rect: &IntRect) -> SmallSet<[Entity; 8]> {
+        self.index.entities(rect)
+    }
+
+    pub fn entities_at_point(&self, x: i32, y: i32) -> SmallSet<[Entity; 8]> {
+        self.index.entities_at_point(x, y)
+    }
+
+    pub fn entities_on_ray(&self, x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<Entity> {
+        self.index.entities_on_ray(x0, y0, x1, y1)
+    }
+
+    pub fn occupancy(&self) -> Vec<(IntRect, usize)> {
+        self.index.occupancy()
+    }
+
+    #[allow(dead_code)]
+    pub fn debug(&self) {
+        self.index.debug("dynamic index");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CELL: i32 = 16;
+
+    // an index holding one entity for each of the given rects
+    fn index_of(rects: &[IntRect]) -> (SpatialIndex, Vec<Entity>) {
+        let mut world = World::new();
+        let mut index = SpatialIndex::new(CELL);
+        let ids = rects
+            .iter()
+            .map(|rect| {
+                let id = world.spawn((rect.clone(),));
+                index.insert_at(id, rect);
+                id
+            })
+            .collect();
+        (index, ids)
+    }
+
+    #[test]
+    fn ray_with_negative_directions() {
+        let (index, ids) = index_of(&[
+            IntRect::new(18, 18, 2, 2),
+            IntRect::new(5, 5, 2, 2),
+            IntRect::new(-5, -5, 2, 2),
+            // off to the side of the ray
+            IntRect::new(20, -10, 2, 2),
+        ]);
+        let found = index.entities_on_ray(20.0, 20.0, -5.0, -5.0);
+        assert_eq!(found, vec![ids[0], ids[1], ids[2]]);
+    }
+
+    #[test]
+    fn ray_starting_on_a_cell_edge() {
+        let (index, ids) = index_of(&[
+            IntRect::new(4, 4, 2, 2),
+            IntRect::new(20, 4, 2, 2),
+            IntRect::new(36, 4, 2, 2),
+        ]);
+        // going right from the edge starts in the cell to its right
+        let found = index.entities_on_ray(16.0, 5.0, 40.0, 5.0);
+        assert_eq!(found, vec![ids[1], ids[2]]);
+        // going left, the start point is still in that cell
+        let found = index.entities_on_ray(32.0, 5.0, 0.0, 5.0);
+        assert_eq!(found, vec![ids[2], ids[1], ids[0]]);
+    }
+
+    #[test]
+    fn ray_along_an_axis() {
+        let (index, ids) = index_of(&[
+            IntRect::new(4, 20, 2, 2),
+            IntRect::new(4, -20, 2, 2),
+            IntRect::new(20, 20, 2, 2),
+            IntRect::new(-20, 4, 2, 2),
+        ]);
+        // dx is 0
+        let found = index.entities_on_ray(5.0, 40.0, 5.0, -20.0);
+        assert_eq!(found, vec![ids[0], ids[1]]);
+        // dy is 0
+        let found = index.entities_on_ray(-20.0, 21.0, 40.0, 21.0);
+        assert_eq!(found, vec![ids[0], ids[2]]);
+        // both are 0
+        let found = index.entities_on_ray(-19.0, 5.0, -19.0, 5.0);
+        assert_eq!(found, vec![ids[3]]);
+    }
+
+    #[test]
+    fn point_finds_its_bucket() {
+        let (index, ids) = index_of(&[IntRect::new(-4, -4, 2, 2), IntRect::new(4, 4, 2, 2)]);
+        let found = index.entities_at_point(-1, -16);
+        assert!(found.contains(&ids[0]) && !found.contains(&ids[1]));
+        assert_eq!(index.entities_at_point(16, 0).len(), 0);
     }
 }
@@ -15,31 +15,41 @@ pub enum KeyState {
 pub enum VirtualKey {
     Left,
     Right,
+    Down,
     Jump,
     Fire,
+    AltFire,
     Interact,
     PrevWeapon,
     NextWeapon,
+    Dash,
+    Melee,
     DebugRestart,
     DebugWin,
     DebugKill,
     DebugAmmo,
     DebugProfile,
+    DebugIndex,
 }
 
-const ALL_KEYS: [(KeyCode, VirtualKey); 12] = [
+const ALL_KEYS: [(KeyCode, VirtualKey); 17] = [
     (KeyCode::Left, VirtualKey::Left),
     (KeyCode::Right, VirtualKey::Right),
+    (KeyCode::Down, VirtualKey::Down),
     (KeyCode::Z, VirtualKey::Jump),
     (KeyCode::X, VirtualKey::Fire),
+    (KeyCode::B, VirtualKey::AltFire),
     (KeyCode::C, VirtualKey::Interact),
     (KeyCode::A, VirtualKey::PrevWeapon),
     (KeyCode::S, VirtualKey::NextWeapon),
+    (KeyCode::V, VirtualKey::Dash),
+    (KeyCode::D, VirtualKey::Melee),
     (KeyCode::R, VirtualKey::DebugRestart),
     (KeyCode::W, VirtualKey::DebugWin),
     (KeyCode::K, VirtualKey::DebugKill),
     (KeyCode::F, VirtualKey::DebugAmmo),
     (KeyCode::P, VirtualKey::DebugProfile),
+    (KeyCode::I, VirtualKey::DebugIndex),
 ];
 
 pub struct Input {
@@ -1,21 +1,32 @@
+use crate::breakable::Breakable;
 use crate::camera::add_camera;
-use crate::draw::PlayerSprite;
-use crate::enemy::{add_enemy, EnemyKind, ParrotKind};
-use crate::index::SpatialIndex;
+use crate::checkpoint::add_checkpoint;
+use crate::door::{KeyColor, LockedDoor};
+use crate::enemy::{add_enemy, EnemySettings, TurretDirection};
+use crate::enemy_defs::{BehaviourKind, EnemyDefs, EnemyType};
+use crate::index::{DynamicIndex, Indexed, SpatialIndex, DEFAULT_CELL_SIZE};
 use crate::level::LevelInfo;
 use crate::messages::Messages;
-use crate::physics::{Actor, IntRect, TileBody, TriggerZone};
-use crate::pickup::{add_ammo, add_heart, add_weapon};
-use crate::player::Controller;
+use crate::movement::{load_movement_params, MovementParams};
+use crate::physics::{IntRect, TileBody, TriggerZone};
+use crate::pickup::{
+    add_ability, add_ammo, add_health_pickup, add_key, add_powerup, add_upgrade, add_weapon,
+    PickupType,
+};
+use crate::player::add_player;
+use crate::powerup::{PowerUpType, PowerUps};
+use crate::pushable::add_crate;
 use crate::resources::TilesetInfo;
-use crate::resources::{Inventory, SceneResources};
+use crate::resources::{Abilities, Inventory, SceneResources};
 use crate::scene::Scene;
 use crate::script::ScriptEngine;
 use crate::stats::LevelStats;
-use crate::switch::add_switch;
+use crate::switch::{add_pressure_plate, add_switch};
 use crate::visibility::compute_obscurers;
-use crate::weapon::{new_weapon, AmmoType, WeaponSelectorUI, WeaponType};
+use crate::weapon::{upgraded_weapon, AmmoType, UpgradeType, WeaponSelectorUI};
+use crate::weapon_defs::WeaponDefs;
 use bitflags::bitflags;
+use enum_map::EnumMap;
 use hecs::{Entity, World};
 use macroquad::prelude::*;
 use macroquad::{file::load_file, texture::load_texture};
@@ -30,6 +41,8 @@ bitflags! {
         const BLOCKER = 0b00000010;
         const OBSCURER = 0b00000100;
         const PLATFORM = 0b00001000;
+        const DESTRUCTIBLE = 0b00010000;
+        const CRUMBLING = 0b00100000;
     }
 }
 
@@ -53,6 +66,16 @@ impl TileFlags {
     pub fn is_platform(&self) -> bool {
         self.contains(Self::PLATFORM)
     }
+
+    #[inline]
+    pub fn is_destructible(&self) -> bool {
+        self.contains(Self::DESTRUCTIBLE)
+    }
+
+    #[inline]
+    pub fn is_crumbling(&self) -> bool {
+        self.contains(Self::CRUMBLING)
+    }
 }
 
 struct AsyncPreloadReader {
@@ -100,6 +123,8 @@ impl LoadingManager {
         &mut self,
         info: &LevelInfo,
         inv: Inventory,
+        weapon_defs: WeaponDefs,
+        enemy_defs: EnemyDefs,
     ) -> Result<Scene, String> {
         let map_name = format!("{}.tmx", info.path).to_owned();
         self.loader.reader_mut().preload(&map_name).await;
@@ -123,9 +148,12 @@ impl LoadingManager {
         let mut world: World = World::new();
         let mut ids: HashMap<String, Entity> = HashMap::new();
         let mut paths: HashMap<String, Vec<(f32, f32)>> = HashMap::new();
-        let mut body_index = SpatialIndex::new();
+        // large maps can tune the index cell sizes with map properties
+        let mut body_index = SpatialIndex::new(cell_size_property(&map, "index_cell_size"));
+        let dynamic_index = DynamicIndex::new(cell_size_property(&map, "dynamic_index_cell_size"));
         let mut selector = WeaponSelectorUI::new();
         let (mut psx, mut psy) = (0, 0);
+        let mut level_abilities = Abilities::empty();
         let mut max_kills = 0;
         let mut max_items = 0;
         let mut max_secrets = 0;
@@ -194,7 +222,7 @@ impl LoadingManager {
                                 Some(ltd) => {
                                     // if map parsing is ever slow, we could cache this per tile
                                     let t = ltd.get_tile().unwrap();
-                                    if t.properties.contains_key("background") {
+                                    let flags = if t.properties.contains_key("background") {
                                         not_solid += 1;
                                         TileFlags::VISIBLE
                                     } else if t.properties.contains_key("transparent") {
@@ -208,6 +236,13 @@ impl LoadingManager {
                                         TileFlags::BLOCKER
                                             | TileFlags::VISIBLE
                                             | TileFlags::OBSCURER
+                                    };
+                                    if t.properties.contains_key("destructible") {
+                                        flags | TileFlags::DESTRUCTIBLE
+                                    } else if t.properties.contains_key("crumbling") {
+                                        flags | TileFlags::CRUMBLING
+                                    } else {
+                                        flags
                                     }
                                 }
                             };
@@ -215,7 +250,18 @@ impl LoadingManager {
                             tiles.push(t.map(|t| t.id() as u16).unwrap_or(0));
                         }
                     }
-                    let door = layer.properties.contains_key("door");
+                    let key = match layer.properties.get("key") {
+                        Some(tiled::PropertyValue::StringValue(k)) => {
+                            let key = KeyColor::from_name(k);
+                            if key.is_none() {
+                                println!("Warning: layer {} has unknown key {}", layer.name, k);
+                            }
+                            key
+                        }
+                        _ => None,
+                    };
+                    // locked doors are always doors
+                    let door = layer.properties.contains_key("door") || key.is_some();
                     let indexed = solid > 0;
                     if solid > 0 && solid < not_solid {
                         println!(
@@ -234,7 +280,19 @@ impl LoadingManager {
                         indexed,
                     );
                     let rect = body.get_rect();
+                    let breakable = body
+                        .data
+                        .iter()
+                        .any(|f| f.is_destructible() || f.is_crumbling());
                     let id = world.spawn((body,));
+                    if breakable {
+                        world.insert_one(id, Breakable::new()).unwrap();
+                    }
+                    if let Some(key) = key {
+                        world
+                            .insert_one(id, LockedDoor::new(layer.name.clone(), key))
+                            .unwrap();
+                    }
                     ids.insert(layer.name.clone(), id);
                     draw_order.push(id);
                     if indexed {
@@ -275,7 +333,7 @@ impl LoadingManager {
                                     *width as i32,
                                     *height as i32,
                                 );
-                                world.spawn((tz, rect));
+                                world.spawn((tz, rect, Indexed {}));
                             }
                             tiled::ObjectData {
                                 shape: tiled::ObjectShape::Point(x, y),
@@ -290,35 +348,40 @@ impl LoadingManager {
                                         // in case we re-entered the intro level with weapons by looping
                                         selector.hidden = true;
                                     }
-                                } else if obj_type == "enemy" {
-                                    add_enemy(
+                                    if properties.contains_key("wall_jump") {
+                                        level_abilities.insert(Abilities::WALL_JUMP);
+                                    }
+                                } else if obj_type == "heart" {
+                                    add_health_pickup(
                                         &mut world,
-                                        EnemyKind::JumpyDog,
                                         *x as i32,
                                         *y as i32,
+                                        PickupType::Heart,
                                     );
-                                    max_kills += 1;
-                                } else if obj_type == "walker_enemy" {
-                                    add_enemy(&mut world, EnemyKind::Dog, *x as i32, *y as i32);
-                                    max_kills += 1;
-                                } else if obj_type == "parrot_enemy" {
-                                    add_enemy(
+                                    max_items += 1;
+                                } else if obj_type == "armor" {
+                                    add_health_pickup(
                                         &mut world,
-                                        EnemyKind::SpiderParrot(ParrotKind::Laser),
                                         *x as i32,
                                         *y as i32,
+                                        PickupType::Armor(1),
                                     );
-                                    max_kills += 1;
-                                } else if obj_type == "cannon_parrot_enemy" {
-                                    add_enemy(
+                                    max_items += 1;
+                                } else if obj_type == "max_health" {
+                                    add_health_pickup(
                                         &mut world,
-                                        EnemyKind::SpiderParrot(ParrotKind::Cannon),
                                         *x as i32,
                                         *y as i32,
+                                        PickupType::MaxHealth,
+                                    );
+                                    max_items += 1;
+                                } else if obj_type == "mega_health" {
+                                    add_health_pickup(
+                                        &mut world,
+                                        *x as i32,
+                                        *y as i32,
+                                        PickupType::MegaHealth,
                                     );
-                                    max_kills += 1;
-                                } else if obj_type == "heart" {
-                                    add_heart(&mut world, *x as i32, *y as i32);
                                     max_items += 1;
                                 } else if obj_type == "ammo_cells_6" {
                                     add_ammo(&mut world, *x as i32, *y as i32, AmmoType::Cell, 6);
@@ -329,58 +392,104 @@ impl LoadingManager {
                                 } else if obj_type == "ammo_rocket" {
                                     add_ammo(&mut world, *x as i32, *y as i32, AmmoType::Rocket, 1);
                                     max_items += 1;
-                                } else if obj_type == "weapon_shotgun" {
-                                    add_weapon(
+                                } else if let Some(typ) = obj_type
+                                    .strip_prefix("weapon_")
+                                    .and_then(|id| weapon_defs.find(id))
+                                {
+                                    add_weapon(&mut world, *x as i32, *y as i32, typ);
+                                    max_items += 1;
+                                } else if obj_type == "ability_wall_jump" {
+                                    add_ability(
                                         &mut world,
                                         *x as i32,
                                         *y as i32,
-                                        WeaponType::Shotgun,
+                                        Abilities::WALL_JUMP,
                                     );
                                     max_items += 1;
-                                } else if obj_type == "weapon_super_shotgun" {
-                                    add_weapon(
+                                } else if obj_type == "ability_double_jump" {
+                                    add_ability(
                                         &mut world,
                                         *x as i32,
                                         *y as i32,
-                                        WeaponType::SuperShotgun,
+                                        Abilities::DOUBLE_JUMP,
                                     );
                                     max_items += 1;
-                                } else if obj_type == "weapon_reverse_shotgun" {
-                                    add_weapon(
+                                } else if obj_type == "ability_dash" {
+                                    add_ability(&mut world, *x as i32, *y as i32, Abilities::DASH);
+                                    max_items += 1;
+                                } else if let Some(key) =
+                                    obj_type.strip_prefix("key_").and_then(KeyColor::from_name)
+                                {
+                                    add_key(&mut world, *x as i32, *y as i32, key);
+                                    max_items += 1;
+                                } else if obj_type == "powerup_damage" {
+                                    add_powerup(
                                         &mut world,
                                         *x as i32,
                                         *y as i32,
-                                        WeaponType::ReverseShotgun,
+                                        PowerUpType::Damage,
                                     );
                                     max_items += 1;
-                                } else if obj_type == "weapon_auto_laser" {
-                                    add_weapon(
+                                } else if obj_type == "powerup_invulnerable" {
+                                    add_powerup(
                                         &mut world,
                                         *x as i32,
                                         *y as i32,
-                                        WeaponType::AutoLaser,
+                                        PowerUpType::Invulnerable,
                                     );
                                     max_items += 1;
-                                } else if obj_type == "weapon_burst_laser" {
-                                    add_weapon(
+                                } else if obj_type == "powerup_speed" {
+                                    add_powerup(
                                         &mut world,
                                         *x as i32,
                                         *y as i32,
-                                        WeaponType::BurstLaser,
+                                        PowerUpType::Speed,
                                     );
                                     max_items += 1;
-                                } else if obj_type == "weapon_double_laser" {
-                                    add_weapon(
+                                } else if obj_type == "powerup_infinite_ammo" {
+                                    add_powerup(
                                         &mut world,
                                         *x as i32,
                                         *y as i32,
-                                        WeaponType::DoubleLaser,
+                                        PowerUpType::InfiniteAmmo,
                                     );
                                     max_items += 1;
+                                } else if let Some(typ) = obj_type
+                                    .strip_prefix("upgrade_")
+                                    .and_then(UpgradeType::from_name)
+                                {
+                                    add_upgrade(&mut world, *x as i32, *y as i32, typ);
+                                    max_items += 1;
+                                } else if obj_type == "crate" {
+                                    add_crate(&mut world, *x as i32, *y as i32);
+                                } else if obj_type == "pressure_plate" {
+                                    add_pressure_plate(
+                                        &mut world,
+                                        name.clone(),
+                                        *x as i32,
+                                        *y as i32,
+                                    );
+                                } else if obj_type == "checkpoint" {
+                                    add_checkpoint(&mut world, name.clone(), *x as i32, *y as i32);
                                 } else if obj_type == "switch" {
                                     let id =
                                         add_switch(&mut world, name.clone(), *x as i32, *y as i32);
                                     ids.insert(name.clone(), id);
+                                } else if let Some(typ) = enemy_defs.find(obj_type) {
+                                    // checked last so enemies.txt can't take over the built in
+                                    // object types
+                                    let settings =
+                                        enemy_settings(&enemy_defs, typ, obj_type, properties);
+                                    add_enemy(
+                                        &mut world,
+                                        &enemy_defs,
+                                        typ,
+                                        *x as i32,
+                                        *y as i32,
+                                        &settings,
+                                    );
+                                    // enemies from spawners aren't counted, only the spawner
+                                    max_kills += 1;
                                 } else {
                                     println!("found an unknown point object type: {}", obj_type)
                                 }
@@ -406,23 +515,20 @@ impl LoadingManager {
         let (player_id, eye_pos, camera_pos) = {
             let mut world = world_ref.lock().unwrap();
 
-            let player_rect = IntRect::new(player_start.0 - 8, player_start.1 - 24, 14, 24);
-            let player_eye = player_rect.centre();
-            let camera_pos = add_camera(&mut world, player_rect.centre());
-            let player = Actor::new(&player_rect, 0.6);
-            let controller = Controller::new();
-            let sprite = PlayerSprite::new();
-            let player_id = world.spawn((player_rect, player, controller, sprite));
+            let player_id = add_player(&mut world, player_start.0, player_start.1, &inv);
+            let player_eye = world.get::<&IntRect>(player_id).unwrap().centre();
+            let camera_pos = add_camera(&mut world, player_eye);
 
             (player_id, player_eye, camera_pos)
         };
 
         compute_obscurers(&mut world_ref.lock().unwrap());
 
+        let start_inventory = inv.clone();
         let stats = LevelStats::new(info.clone(), max_kills, max_items, max_secrets);
         let mut weapons = VecDeque::with_capacity(4);
-        for t in inv.weapon_types {
-            weapons.push_back(new_weapon(t));
+        for (t, upgrades) in inv.weapon_types {
+            weapons.push_back(upgraded_weapon(&weapon_defs, t, upgrades));
         }
 
         let resources = SceneResources {
@@ -433,12 +539,26 @@ impl LoadingManager {
             camera_pos,
             draw_order,
             body_index,
+            dynamic_index,
             tileset_info,
+            movement: load_movement_params().await.unwrap_or_else(|e| {
+                println!("{}", e);
+                MovementParams::default()
+            }),
             messages: Messages::new(),
             stats,
             triggers: HashSet::new(),
+            deaths: Vec::new(),
+            weapon_defs,
+            enemy_defs,
             weapons,
             ammo: inv.ammo,
+            abilities: inv.abilities,
+            level_abilities,
+            keys: EnumMap::default(),
+            powerups: PowerUps::new(),
+            checkpoint: None,
+            start_inventory,
             selector,
             death_timer: None,
         };
@@ -446,6 +566,60 @@ impl LoadingManager {
     }
 }
 
-pub async fn load_level(info: LevelInfo, inv: Inventory) -> Result<Scene, String> {
-    LoadingManager::new().load_level(&info, inv).await
+pub async fn load_level(
+    info: LevelInfo,
+    inv: Inventory,
+    weapon_defs: WeaponDefs,
+    enemy_defs: EnemyDefs,
+) -> Result<Scene, String> {
+    LoadingManager::new()
+        .load_level(&info, inv, weapon_defs, enemy_defs)
+        .await
+}
+
+// turret direction and rate, or spawner rate, enemy and max, overriding the enemy's definition
+fn enemy_settings(
+    defs: &EnemyDefs,
+    typ: EnemyType,
+    id: &str,
+    properties: &tiled::Properties,
+) -> EnemySettings {
+    let mut settings = EnemySettings::new(defs, typ);
+    if let Some(tiled::PropertyValue::StringValue(d)) = properties.get("direction") {
+        match TurretDirection::from_name(d) {
+            Some(d) => settings.direction = d,
+            None => println!("Warning: {} has unknown direction {}", id, d),
+        }
+    }
+    if let Some(tiled::PropertyValue::StringValue(e)) = properties.get("enemy") {
+        match defs.find(e) {
+            Some(t) if defs.get(t).behaviour == BehaviourKind::Spawner => {
+                println!("Warning: {} can't spawn another spawner {}", id, e)
+            }
+            Some(t) => settings.spawn = Some(t),
+            None => println!("Warning: {} spawns unknown enemy {}", id, e),
+        }
+    }
+    match properties.get("rate") {
+        Some(tiled::PropertyValue::IntValue(n)) if *n > 0 && *n <= u8::MAX as i32 => {
+            settings.rate = *n as u8
+        }
+        Some(_) => println!("Warning: {} has bad rate", id),
+        None => (),
+    }
+    match properties.get("max") {
+        Some(tiled::PropertyValue::IntValue(n)) if *n >= 0 && *n <= u8::MAX as i32 => {
+            settings.spawn_max = *n as u8
+        }
+        Some(_) => println!("Warning: {} has bad max", id),
+        None => (),
+    }
+    settings
+}
+
+fn cell_size_property(map: &tiled::Map, key: &str) -> i32 {
+    match map.properties.get(key) {
+        Some(tiled::PropertyValue::IntValue(n)) if *n > 0 => *n,
+        _ => DEFAULT_CELL_SIZE,
+    }
 }
@@ -1,19 +1,26 @@
 use std::num::NonZeroU8;
 
+use breakable::Breakable;
 use camera::PlayerCamera;
-use enemy::update_enemies;
+use checkpoint::{respawn_at_checkpoint, Checkpoint};
+use door::LockedDoor;
+use enemy::{handle_enemy_deaths, update_enemies};
+use health::{death_triggers, update_health, Health};
 use hecs::CommandBuffer;
 use input::Input;
 use macroquad::experimental::coroutines::{start_coroutine, stop_all_coroutines};
 use macroquad::prelude::*;
 use physics::{Actor, PathMotion};
-use pickup::{Pickup, WeaponPickup};
-use player::Controller;
+use pickup::{drop_loot, Pickup, WeaponPickup};
+use player::{handle_player_death, Controller};
+use powerup::PowerUps;
 use profile::{Phase, Profiler};
-use projectile::Projectile;
+use projectile::{Beam, Projectile};
+use pushable::PushableCrate;
 use render::Renderer;
 use resources::{load_assets, Inventory};
 use scene::{new_prelevel, Scene};
+use switch::PressurePlate;
 use timer::Timer;
 use transition::TransitionEffectType;
 use vfx::update_vfx;
@@ -23,21 +30,33 @@ use enum_iterator::all;
 #[cfg(debug_assertions)]
 use input::VirtualKey;
 #[cfg(debug_assertions)]
+use movement::MovementReloader;
+#[cfg(debug_assertions)]
 use weapon::{add_ammo, AmmoType};
 
+mod breakable;
 mod camera;
+mod checkpoint;
+mod datafile;
+mod door;
 mod draw;
 mod enemy;
+mod enemy_defs;
+mod health;
 mod index;
 mod input;
 mod level;
 mod loader;
+mod melee;
 mod messages;
+mod movement;
 mod physics;
 mod pickup;
 mod player;
+mod powerup;
 mod profile;
 mod projectile;
+mod pushable;
 mod render;
 mod resources;
 mod scene;
@@ -49,6 +68,7 @@ mod transition;
 mod vfx;
 mod visibility;
 mod weapon;
+mod weapon_defs;
 
 pub(crate) const RENDER_W: u32 = 320;
 pub(crate) const RENDER_H: u32 = 200;
@@ -75,6 +95,8 @@ async fn main() {
 
     #[cfg(debug_assertions)]
     let mut show_profile = false;
+    #[cfg(debug_assertions)]
+    let mut movement_reloader = MovementReloader::new();
 
     let coro = start_coroutine(load_assets());
     let mut result = None;
@@ -95,7 +117,7 @@ async fn main() {
         assets.get_first_level()
     };
 
-    let mut scene: Scene = new_prelevel(info, Inventory::new(), false).await;
+    let mut scene: Scene = new_prelevel(info, Inventory::new(), false, &assets).await;
 
     loop {
         match assets.next_scene {
@@ -126,19 +148,34 @@ async fn main() {
             Scene::PlayLevel(ref mut resources) => {
                 for _ in 0..clock.get_num_updates() {
                     let mut buffer = CommandBuffer::new();
+                    resources.deaths.clear();
                     profiler.start(Phase::Motion);
                     PathMotion::apply(resources);
+                    Breakable::update(resources, &mut buffer);
+                    resources.update_dynamic_index();
                     profiler.start(Phase::Pickups);
                     Pickup::update(resources, &mut buffer);
                     WeaponPickup::update(resources);
                     profiler.start(Phase::Player);
+                    PowerUps::update(resources);
                     Controller::update(resources, &mut buffer, &input);
+                    LockedDoor::update(resources, &mut buffer, &input);
+                    Checkpoint::update(resources);
                     profiler.start(Phase::Enemies);
                     update_enemies(resources, &mut buffer);
                     profiler.start(Phase::Actor);
                     Actor::update(resources);
+                    PushableCrate::update(resources, &mut buffer);
+                    PressurePlate::update(resources);
+                    resources.update_dynamic_index();
                     profiler.start(Phase::Projectile);
+                    Beam::update(resources, &mut buffer);
                     Projectile::update(resources, &mut buffer);
+                    update_health(resources);
+                    handle_player_death(resources, &mut buffer);
+                    handle_enemy_deaths(resources, &mut buffer);
+                    drop_loot(resources, &mut buffer);
+                    death_triggers(resources);
                     profiler.start(Phase::Vfx);
                     update_vfx(resources, &mut buffer);
                     profiler.stop();
@@ -150,12 +187,13 @@ async fn main() {
                     {
                         let w = resources.world_ref.lock().unwrap();
                         #[allow(unused_mut)] // needs to be mut in debug mode but not release
-                        if let Ok(mut controller) = w.get::<&mut Controller>(resources.player_id) {
+                        if let Ok(mut health) = w.get::<&mut Health>(resources.player_id) {
                             #[cfg(debug_assertions)]
                             if input.is_pressed(VirtualKey::DebugKill) {
-                                controller.hp = 0
+                                health.invulnerable = false;
+                                health.kill(health::DamageType::Laser);
                             }
-                            player_dead = controller.hp == 0;
+                            player_dead = !health.is_alive();
                         };
                     };
                     if player_dead {
@@ -165,15 +203,23 @@ async fn main() {
                         **dt = dt.saturating_add(1);
                         let n = dt.get();
                         if n == 60 {
-                            resources
-                                .messages
-                                .add("Press any key to restart.".to_owned());
+                            let msg = if resources.checkpoint.is_some() {
+                                "Press any key to return to the checkpoint."
+                            } else {
+                                "Press any key to restart."
+                            };
+                            resources.messages.add(msg.to_owned());
                         }
-                        if n > 30 && input.is_any_pressed() {
+                        if n > 30 && input.is_any_pressed() && !respawn_at_checkpoint(resources) {
                             stop_all_coroutines();
                             assets.next_scene = Some((
-                                new_prelevel(resources.stats.info.clone(), Inventory::new(), false)
-                                    .await,
+                                new_prelevel(
+                                    resources.stats.info.clone(),
+                                    resources.start_inventory.clone(),
+                                    false,
+                                    &assets,
+                                )
+                                .await,
                                 TransitionEffectType::Shatter,
                             ));
                         }
@@ -193,9 +239,28 @@ async fn main() {
                         show_profile = !show_profile;
                     }
                     #[cfg(debug_assertions)]
+                    if input.is_pressed(VirtualKey::DebugIndex) {
+                        renderer.show_index = !renderer.show_index;
+                        resources.body_index.debug("body index");
+                        resources.dynamic_index.debug();
+                    }
+                    #[cfg(debug_assertions)]
+                    if resources.stats.frames % 60 == 0 {
+                        // pick up any edits to the movement tuning file
+                        if let Some(movement) = movement_reloader.check().await {
+                            if movement != resources.movement {
+                                resources.movement = movement;
+                                resources
+                                    .messages
+                                    .add("Reloaded movement parameters.".to_owned());
+                            }
+                        }
+                    }
+                    #[cfg(debug_assertions)]
                     if input.is_pressed(VirtualKey::DebugAmmo) {
                         for typ in all::<AmmoType>() {
                             add_ammo(
+                                &resources.weapon_defs,
                                 &mut resources.weapons,
                                 &mut resources.ammo,
                                 &mut resources.selector,
@@ -209,8 +274,13 @@ async fn main() {
                         stop_all_coroutines();
                         assets.next_scene = Some((
                             // skip the transition for faster debugging
-                            new_prelevel(resources.stats.info.clone(), Inventory::new(), true)
-                                .await,
+                            new_prelevel(
+                                resources.stats.info.clone(),
+                                Inventory::new(),
+                                true,
+                                &assets,
+                            )
+                            .await,
                             TransitionEffectType::Shatter,
                         ));
                     }
@@ -235,10 +305,6 @@ async fn main() {
                     resources.selector.update();
                     resources.stats.frames += 1;
                     renderer.tick();
-
-                    /* if resources.stats.frames % 100 == 0 {
-                        resources.body_index.debug();
-                    } */
                 }
             }
             Scene::PostLevel(stats, inv) => {
@@ -248,7 +314,7 @@ async fn main() {
                 if input.is_any_pressed() {
                     let info = assets.get_next_level(&stats.info);
                     assets.next_scene = Some((
-                        new_prelevel(info, inv.clone(), false).await,
+                        new_prelevel(info, inv.clone(), false, &assets).await,
                         TransitionEffectType::Shatter,
                     ));
                 }
@@ -0,0 +1,78 @@
+use crate::enemy::EnemyHittable;
+use crate::health::{Damage, DamageType, Health};
+use crate::index::DynamicIndex;
+use crate::physics::{Impulse, IntRect};
+use crate::projectile::{DamageEnemies, DamagePlayer, FireballSplit, Projectile};
+use crate::vfx::ZapFlash;
+use hecs::{CommandBuffer, Entity, World};
+
+// how long the swing is drawn for. it only hits on the first frame
+pub const MELEE_FRAMES: u8 = 8;
+pub const MELEE_COOLDOWN: u8 = 15;
+const MELEE_REACH: i32 = 16;
+const MELEE_DAMAGE: u16 = 2;
+const MELEE_KNOCKBACK: f32 = 6.0;
+// deflected shots come back a little faster than they arrived
+const DEFLECT_SPEED_SCALE: f32 = 1.5;
+
+// the area in front of the player that the swing covers
+fn melee_rect(p_rect: &IntRect, facing: i8) -> IntRect {
+    let x = if facing > 0 {
+        p_rect.x + p_rect.w
+    } else {
+        p_rect.x - MELEE_REACH
+    };
+    IntRect::new(x, p_rect.y - 4, MELEE_REACH, p_rect.h + 4)
+}
+
+// hurts and knocks back enemies within reach, and sends enemy shots back where they came from
+pub fn swing(
+    world: &World,
+    index: &DynamicIndex,
+    buffer: &mut CommandBuffer,
+    player_id: Entity,
+    p_rect: &IntRect,
+    facing: i8,
+    damage: u16,
+) {
+    let area = melee_rect(p_rect, facing);
+    let centre = p_rect.centre();
+    for id in index.entities(&area).iter().copied() {
+        // the player's rect is already borrowed by the caller
+        if id == player_id {
+            continue;
+        }
+        let rect = match world.get::<&IntRect>(id) {
+            Ok(rect) => rect.clone(),
+            Err(_) => continue,
+        };
+        if !rect.intersects(&area) {
+            continue;
+        }
+        if let Ok(mut q) = world.query_one::<(&mut EnemyHittable, &mut Health)>(id) {
+            if let Some((en, health)) = q.get() {
+                if health.is_alive() {
+                    en.hurt(
+                        health,
+                        Damage::new(MELEE_DAMAGE * damage, DamageType::Melee),
+                    );
+                    en.knock_back(Impulse::new(centre, MELEE_KNOCKBACK));
+                }
+            }
+            continue;
+        }
+        if world.satisfies::<&DamagePlayer>(id).unwrap_or(false) {
+            if let Ok(mut proj) = world.get::<&mut Projectile>(id) {
+                let speed = (proj.vx * proj.vx + proj.vy * proj.vy).sqrt() * DEFLECT_SPEED_SCALE;
+                proj.vx = facing as f32 * speed;
+                proj.vy = 0.0;
+                buffer.remove_one::<DamagePlayer>(id);
+                // or the pieces would come straight back at the player
+                buffer.remove_one::<FireballSplit>(id);
+                buffer.insert_one(id, DamageEnemies {});
+                let (x, y) = rect.centre_int();
+                buffer.spawn((ZapFlash::new_from_centre(x, y),));
+            }
+        }
+    }
+}
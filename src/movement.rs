@@ -0,0 +1,136 @@
+use crate::datafile::{self, set, Line};
+use macroquad::prelude::load_string;
+
+const MOVEMENT_FILE: &str = "movement.txt";
+
+// everything that affects how the player moves, loaded from movement.txt
+#[derive(Clone, PartialEq)]
+pub struct MovementParams {
+    pub accel: f32,
+    pub drag: f32,
+    pub gravity: f32,
+    pub terminal_velocity: f32,
+    pub jump_velocity: f32,
+    pub jump_hold_velocity: f32,
+    pub jump_frames: u32,
+    pub coyote_frames: u8,
+    pub jump_buffer_frames: u8,
+    pub wall_slide_speed: f32,
+    pub wall_jump_kick: f32,
+    pub wall_jump_lock_frames: u8,
+    pub dash_speed: f32,
+    pub dash_frames: u8,
+    pub dash_cooldown_frames: u8,
+}
+
+impl Default for MovementParams {
+    fn default() -> Self {
+        Self {
+            accel: 3.0,
+            drag: 0.6,
+            gravity: 1.0,
+            terminal_velocity: 16.0,
+            jump_velocity: -6.0,
+            jump_hold_velocity: -10.0,
+            jump_frames: 5,
+            coyote_frames: 5,
+            jump_buffer_frames: 5,
+            wall_slide_speed: 1.0,
+            wall_jump_kick: 6.0,
+            wall_jump_lock_frames: 8,
+            dash_speed: 12.0,
+            dash_frames: 8,
+            dash_cooldown_frames: 40,
+        }
+    }
+}
+
+impl MovementParams {
+    // one "name value" pair per line, with no sections
+    // missing or broken entries keep their default value
+    pub fn parse(text: &str) -> Self {
+        let mut params = Self::default();
+        for (line, parsed) in datafile::lines(text) {
+            let (key, value) = match parsed {
+                Line::Setting(key, value) if !value.is_empty() => (key, value),
+                _ => {
+                    println!("{}: couldn't parse line '{}'", MOVEMENT_FILE, line);
+                    continue;
+                }
+            };
+            let ok = match key {
+                "accel" => set(&mut params.accel, value),
+                "drag" => set(&mut params.drag, value),
+                "gravity" => set(&mut params.gravity, value),
+                "terminal_velocity" => set(&mut params.terminal_velocity, value),
+                "jump_velocity" => set(&mut params.jump_velocity, value),
+                "jump_hold_velocity" => set(&mut params.jump_hold_velocity, value),
+                "jump_frames" => set(&mut params.jump_frames, value),
+                "coyote_frames" => set(&mut params.coyote_frames, value),
+                "jump_buffer_frames" => set(&mut params.jump_buffer_frames, value),
+                "wall_slide_speed" => set(&mut params.wall_slide_speed, value),
+                "wall_jump_kick" => set(&mut params.wall_jump_kick, value),
+                "wall_jump_lock_frames" => set(&mut params.wall_jump_lock_frames, value),
+                "dash_speed" => set(&mut params.dash_speed, value),
+                "dash_frames" => set(&mut params.dash_frames, value),
+                "dash_cooldown_frames" => set(&mut params.dash_cooldown_frames, value),
+                _ => false,
+            };
+            if !ok {
+                println!("{}: bad setting '{}'", MOVEMENT_FILE, line);
+            }
+        }
+        params
+    }
+}
+
+pub async fn load_movement_params() -> Result<MovementParams, String> {
+    let text = load_string(MOVEMENT_FILE)
+        .await
+        .map_err(|_| format!("couldn't load {}", MOVEMENT_FILE))?;
+    Ok(MovementParams::parse(&text))
+}
+
+// watches movement.txt for edits in debug builds
+pub struct MovementReloader {
+    text: Option<String>,
+}
+
+impl MovementReloader {
+    pub fn new() -> Self {
+        Self { text: None }
+    }
+
+    // the new params if the file has changed since last time. a file that can't be read,
+    // like one caught in the middle of being saved, is left until the next check
+    pub async fn check(&mut self) -> Option<MovementParams> {
+        let text = load_string(MOVEMENT_FILE).await.ok()?;
+        if self.text.as_ref() == Some(&text) {
+            return None;
+        }
+        let params = MovementParams::parse(&text);
+        self.text = Some(text);
+        Some(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the defaults are what the player moved like before movement.txt existed
+    #[test]
+    fn shipped_params_match_defaults() {
+        let text = include_str!("../assets/movement.txt");
+        assert!(MovementParams::parse(text) == MovementParams::default());
+    }
+
+    #[test]
+    fn broken_lines_keep_defaults() {
+        let params = MovementParams::parse("accel\ndrag lots\n[jump]\ngravity 2.0\n");
+        let default = MovementParams::default();
+        assert_eq!(params.accel, default.accel);
+        assert_eq!(params.drag, default.drag);
+        assert_eq!(params.gravity, 2.0);
+    }
+}
@@ -5,13 +5,13 @@ use hecs::{Entity, World};
 use macroquad::math::{vec2, Vec2};
 use std::{cmp::Ordering, collections::HashSet};
 
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Clone, Copy)]
 enum CollisionType {
     Blocker,
     TopOfBlockerOrPlatform,
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 pub struct IntRect {
     pub x: i32,
     pub y: i32,
@@ -44,7 +44,7 @@ fn pushing_rect(rect: &IntRect) -> IntRect {
     IntRect::new(rect.x, rect.y, rect.w, rect.h + 1)
 }
 
-fn feet_rect(rect: &IntRect) -> IntRect {
+pub fn feet_rect(rect: &IntRect) -> IntRect {
     IntRect::new(rect.x, rect.y + rect.h, rect.w, 1)
 }
 
@@ -127,6 +127,46 @@ impl TileBody {
         }
     }
 
+    pub fn tile_rect(&self, index: usize) -> IntRect {
+        let kx = index as i32 % self.width;
+        let ky = index as i32 / self.width;
+        IntRect::new(
+            self.x + kx * self.size,
+            self.y + ky * self.size,
+            self.size,
+            self.size,
+        )
+    }
+
+    pub fn tiles_in_rect(&self, rect: &IntRect) -> Vec<usize> {
+        let min_kx = (rect.x - self.x).div_euclid(self.size).max(0);
+        let max_kx = (rect.x + rect.w - 1 - self.x)
+            .div_euclid(self.size)
+            .min(self.width - 1);
+        let min_ky = (rect.y - self.y).div_euclid(self.size).max(0);
+        let max_ky = (rect.y + rect.h - 1 - self.y)
+            .div_euclid(self.size)
+            .min(self.data.len() as i32 / self.width - 1);
+        let mut result = Vec::new();
+        for ky in min_ky..=max_ky {
+            for kx in min_kx..=max_kx {
+                result.push((ky * self.width + kx) as usize);
+            }
+        }
+        result
+    }
+
+    fn sweep(&self, rect: &IntRect, area: &IntRect, vx: f32, vy: f32) -> Option<SweepHit> {
+        let mut result = None;
+        for index in self.tiles_in_rect(area) {
+            if self.data[index].is_blocker() {
+                let hit = sweep_rect(rect, vx, vy, &self.tile_rect(index));
+                result = earliest(result, hit);
+            }
+        }
+        result
+    }
+
     fn collide(&self, rect: &IntRect, typ: CollisionType) -> bool {
         let adjustment = match typ {
             CollisionType::Blocker => 0,
@@ -178,7 +218,7 @@ impl TileBody {
         max_dist
     }
 
-    fn collide_dist_down(&self, rect: &IntRect, max_dist: i32) -> i32 {
+    fn collide_dist_down(&self, rect: &IntRect, max_dist: i32, platforms: bool) -> i32 {
         let mut possible_dist = (self.y - (rect.y + rect.h)).rem_euclid(self.size);
         let mut ky = (rect.y + rect.h - self.y + possible_dist).div_euclid(self.size);
         let min_kx = (rect.x - self.x).div_euclid(self.size).max(0);
@@ -191,7 +231,7 @@ impl TileBody {
                     let index = ky * self.width + kx;
                     if index < self.data.len() as i32
                         && (self.data[index as usize].is_blocker()
-                            || self.data[index as usize].is_platform())
+                            || platforms && self.data[index as usize].is_platform())
                     {
                         return possible_dist;
                     }
@@ -244,6 +284,98 @@ impl TileBody {
     }
 }
 
+// a shove away from the point where something was hit
+#[derive(Clone, Copy)]
+pub struct Impulse {
+    pub source: Vec2,
+    pub force: f32,
+    // pushes straight away from the source in any direction, rather than mostly sideways
+    pub radial: bool,
+}
+
+impl Impulse {
+    pub fn new(source: Vec2, force: f32) -> Self {
+        Self {
+            source,
+            force,
+            radial: false,
+        }
+    }
+
+    // for explosions, which can throw things upwards if they go off underneath
+    pub fn blast(source: Vec2, force: f32) -> Self {
+        Self {
+            source,
+            force,
+            radial: true,
+        }
+    }
+
+    pub fn apply(&self, actor: &mut Actor, rect: &IntRect) {
+        if self.force <= 0.0 {
+            return;
+        }
+        if self.radial {
+            let dir = (rect.centre() - self.source).normalize_or_zero();
+            // something exactly at the centre of the blast just goes up
+            let dir = if dir == Vec2::ZERO {
+                vec2(0.0, -1.0)
+            } else {
+                dir
+            };
+            actor.vx += dir.x * self.force;
+            actor.vy = actor.vy.min(0.0) + dir.y * self.force;
+            return;
+        }
+        let dir = if rect.centre().x < self.source.x {
+            -1.0
+        } else {
+            1.0
+        };
+        actor.vx += dir * self.force;
+        // pop up a little so we don't just grind along the floor
+        actor.vy = actor.vy.min(-self.force * 0.5);
+    }
+}
+
+// actors with this component block the movement of other actors
+pub struct SolidActor {
+    pub pushable: bool,
+}
+
+struct SolidRect {
+    id: Entity,
+    rect: IntRect,
+    pushable: bool,
+}
+
+fn solid_rects(world: &World) -> Vec<SolidRect> {
+    world
+        .query::<(&SolidActor, &IntRect)>()
+        .iter()
+        .map(|(id, (solid, rect))| SolidRect {
+            id,
+            rect: rect.clone(),
+            pushable: solid.pushable,
+        })
+        .collect()
+}
+
+fn update_solid_rect(solids: &mut [SolidRect], id: Entity, rect: &IntRect) {
+    if let Some(s) = solids.iter_mut().find(|s| s.id == id) {
+        s.rect = rect.clone();
+    }
+}
+
+// actors with this component (or solid ones) are heavy enough to hold down
+// pressure plates or set off crumbling tiles
+pub struct Heavy {}
+
+pub fn is_heavy(world: &World, id: Entity) -> bool {
+    world.satisfies::<&Heavy>(id).unwrap_or(false)
+        || world.satisfies::<&SolidActor>(id).unwrap_or(false)
+}
+
 pub struct Actor {
     prec_x: f32,
     prec_y: f32,
@@ -252,6 +384,10 @@ pub struct Actor {
     pub grounded: bool,
     pub crushed: bool,
     pub drag: f32,
+    // while non-zero the actor falls through platforms
+    pub drop_frames: u8,
+    // off for things that fly
+    pub gravity: bool,
 }
 
 impl Actor {
@@ -264,25 +400,81 @@ impl Actor {
             grounded: false,
             crushed: false,
             drag,
+            drop_frames: 0,
+            gravity: true,
         }
     }
 
+    // change the height of an actor while keeping its feet in the same place
+    pub fn set_height(&mut self, rect: &mut IntRect, h: i32) {
+        let dy = rect.h - h;
+        rect.y += dy;
+        rect.h = h;
+        self.prec_y += dy as f32;
+    }
+
     pub fn update(resources: &SceneResources) {
         let world = resources.world_ref.lock().unwrap();
-        for (_, (actor, rect)) in world.query::<(&mut Actor, &mut IntRect)>().iter() {
-            actor.vy += 1.0;
+        let mut solids = solid_rects(&world);
+        // resolve actors from the bottom up, so anything stacked on top of a solid
+        // actor moves relative to where that actor ended up this frame
+        let mut order: Vec<(Entity, i32)> = world
+            .query::<(&Actor, &IntRect)>()
+            .iter()
+            .map(|(id, (_, rect))| (id, rect.y + rect.h))
+            .collect();
+        order.sort_by_key(|(_, bottom)| -bottom);
+        for (id, _) in order {
+            let mut q = world.query_one::<(&mut Actor, &mut IntRect)>(id).unwrap();
+            let (actor, rect) = q.get().unwrap();
+            if actor.gravity {
+                actor.vy += resources.movement.gravity;
+            } else {
+                // flyers slow down the same way in every direction
+                actor.vy *= actor.drag;
+            }
             actor.vx *= actor.drag;
-            actor.vy = actor.vy.min(16.0);
+            actor.vy = actor.vy.min(resources.movement.terminal_velocity);
             let vx = actor.vx;
             let vy = actor.vy;
-            let (cx, cy) = move_actor(actor, rect, vx, vy, &world, &resources.body_index);
+            let targ_x = (actor.prec_x + vx).round() as i32;
+            let (cx, cy) = move_actor(actor, rect, vx, vy, &world, &resources.body_index, &solids);
             if cx {
                 actor.vx = 0.0;
             }
             if cy {
                 actor.vy = 0.0;
             }
-            actor.grounded = check_player_grounded(rect, &world);
+            let platforms = actor.drop_frames == 0;
+            actor.drop_frames = actor.drop_frames.saturating_sub(1);
+            actor.grounded =
+                check_player_grounded(rect, &world, platforms) || check_on_solid(rect, &solids);
+            update_solid_rect(&mut solids, id, rect);
+            // if the player was stopped by something pushable, shove it out of the way
+            let push = targ_x - rect.x;
+            let pushed = if cx && push != 0 && id == resources.player_id {
+                find_pushable(rect, &solids, push.signum())
+            } else {
+                None
+            };
+            drop(q);
+            if let Some(other) = pushed {
+                let mut q = world
+                    .query_one::<(&mut Actor, &mut IntRect)>(other)
+                    .unwrap();
+                if let Some((o_actor, o_rect)) = q.get() {
+                    move_actor(
+                        o_actor,
+                        o_rect,
+                        push as f32,
+                        0.0,
+                        &world,
+                        &resources.body_index,
+                        &solids,
+                    );
+                    update_solid_rect(&mut solids, other, o_rect);
+                }
+            }
         }
     }
 }
@@ -416,6 +608,104 @@ impl PathMotion {
     }
 }
 
+pub struct SweepHit {
+    pub t: f32,
+    pub normal: Vec2,
+}
+
+impl SweepHit {
+    // the point on the leading edge of the rect that made contact
+    pub fn contact_point(&self, rect: &IntRect) -> (i32, i32) {
+        let (cx, cy) = rect.centre_int();
+        let x = match self.normal.x.total_cmp(&0.0) {
+            Ordering::Less => rect.x + rect.w - 1,
+            Ordering::Equal => cx,
+            Ordering::Greater => rect.x,
+        };
+        let y = match self.normal.y.total_cmp(&0.0) {
+            Ordering::Less => rect.y + rect.h - 1,
+            Ordering::Equal => cy,
+            Ordering::Greater => rect.y,
+        };
+        (x, y)
+    }
+}
+
+// times at which a moving span starts and stops overlapping a stationary one
+fn sweep_axis(p: i32, s: i32, v: f32, q: i32, t: i32) -> Option<(f32, f32)> {
+    match v.total_cmp(&0.0) {
+        Ordering::Equal => {
+            if p < q + t && p + s > q {
+                Some((f32::NEG_INFINITY, f32::INFINITY))
+            } else {
+                None
+            }
+        }
+        Ordering::Greater => Some(((q - (p + s)) as f32 / v, (q + t - p) as f32 / v)),
+        Ordering::Less => Some(((q + t - p) as f32 / v, (q - (p + s)) as f32 / v)),
+    }
+}
+
+// swept AABB test of a rect moving by (vx, vy) against a stationary rect, giving the
+// fraction of the motion completed at the moment of impact and the surface normal
+pub fn sweep_rect(rect: &IntRect, vx: f32, vy: f32, other: &IntRect) -> Option<SweepHit> {
+    let (x_entry, x_exit) = sweep_axis(rect.x, rect.w, vx, other.x, other.w)?;
+    let (y_entry, y_exit) = sweep_axis(rect.y, rect.h, vy, other.y, other.h)?;
+    let entry = x_entry.max(y_entry);
+    let exit = x_exit.min(y_exit);
+    if entry >= exit || entry >= 1.0 || exit <= 0.0 {
+        return None;
+    }
+    let along_x = if entry < 0.0 {
+        // already overlapping, so push back against the main direction of motion
+        vx.abs() >= vy.abs()
+    } else {
+        x_entry > y_entry
+    };
+    let normal = if along_x {
+        vec2(-vx.signum(), 0.0)
+    } else {
+        vec2(0.0, -vy.signum())
+    };
+    Some(SweepHit {
+        t: entry.max(0.0),
+        normal,
+    })
+}
+
+fn earliest(a: Option<SweepHit>, b: Option<SweepHit>) -> Option<SweepHit> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if b.t < a.t { b } else { a }),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+pub fn sweep_tiles(
+    world: &World,
+    body_index: &SpatialIndex,
+    rect: &IntRect,
+    vx: f32,
+    vy: f32,
+) -> Option<SweepHit> {
+    let area = sweep_area(rect, vx, vy);
+    let mut result = None;
+    for id in body_index.entities(&area).iter() {
+        let hit = world
+            .get::<&TileBody>(*id)
+            .unwrap()
+            .sweep(rect, &area, vx, vy);
+        result = earliest(result, hit);
+    }
+    result
+}
+
+// everything a rect could touch while moving by (vx, vy)
+pub fn sweep_area(rect: &IntRect, vx: f32, vy: f32) -> IntRect {
+    let m = motion_rect(rect, rect.x + vx.round() as i32, rect.y + vy.round() as i32);
+    IntRect::new(m.x - 1, m.y - 1, m.w + 2, m.h + 2)
+}
+
 pub fn collide_any(world: &World, body_index: &SpatialIndex, rect: &IntRect) -> bool {
     let blockers = body_index.entities(rect);
     blockers.iter().any(|id| {
@@ -433,6 +723,7 @@ fn move_actor(
     vy: f32,
     world: &World,
     body_index: &SpatialIndex,
+    solids: &[SolidRect],
 ) -> (bool, bool) {
     actor.prec_x += vx;
     let targ_x = actor.prec_x.round() as i32;
@@ -453,6 +744,7 @@ fn move_actor(
                         .collide_dist_left(rect, d),
                 );
             }
+            d = d.min(solid_dist(rect, solids, -1, 0, d));
             rect.x -= d;
         }
         Ordering::Equal => (),
@@ -467,6 +759,7 @@ fn move_actor(
                         .collide_dist_right(rect, d),
                 );
             }
+            d = d.min(solid_dist(rect, solids, 1, 0, d));
             rect.x += d;
         }
     }
@@ -486,6 +779,7 @@ fn move_actor(
                         .collide_dist_up(rect, d),
                 );
             }
+            d = d.min(solid_dist(rect, solids, 0, -1, d));
             rect.y -= d;
         }
         Ordering::Equal => (),
@@ -493,13 +787,13 @@ fn move_actor(
             // handle moving down
             let mut d = targ_y - rect.y;
             for id in blockers.iter() {
-                d = d.min(
-                    world
-                        .get::<&TileBody>(*id)
-                        .unwrap()
-                        .collide_dist_down(rect, d),
-                );
+                d = d.min(world.get::<&TileBody>(*id).unwrap().collide_dist_down(
+                    rect,
+                    d,
+                    actor.drop_frames == 0,
+                ));
             }
+            d = d.min(solid_dist(rect, solids, 0, 1, d));
             rect.y += d;
         }
     }
@@ -510,6 +804,37 @@ fn move_actor(
     (collided_x, collided_y)
 }
 
+// how far an actor can move one way before running into a solid actor, ignoring any
+// solids it already overlaps (including itself) so that nothing can get stuck
+fn solid_dist(rect: &IntRect, solids: &[SolidRect], dx: i32, dy: i32, max_dist: i32) -> i32 {
+    let mut d = max_dist;
+    for s in solids.iter().filter(|s| !s.rect.intersects(rect)) {
+        let r = &s.rect;
+        let overlap_x = rect.x < r.x + r.w && rect.x + rect.w > r.x;
+        let overlap_y = rect.y < r.y + r.h && rect.y + rect.h > r.y;
+        let gap = match (dx, dy) {
+            (-1, 0) if overlap_y => rect.x - (r.x + r.w),
+            (1, 0) if overlap_y => r.x - (rect.x + rect.w),
+            (0, -1) if overlap_x => rect.y - (r.y + r.h),
+            (0, 1) if overlap_x => r.y - (rect.y + rect.h),
+            _ => continue,
+        };
+        if gap >= 0 {
+            d = d.min(gap);
+        }
+    }
+    d
+}
+
+fn find_pushable(rect: &IntRect, solids: &[SolidRect], dir: i32) -> Option<Entity> {
+    let x = if dir > 0 { rect.x + rect.w } else { rect.x - 1 };
+    let side = IntRect::new(x, rect.y, 1, rect.h);
+    solids
+        .iter()
+        .find(|s| s.pushable && s.rect.intersects(&side) && !s.rect.intersects(rect))
+        .map(|s| s.id)
+}
+
 fn move_body(
     world: &World,
     spatial_index: &mut SpatialIndex,
@@ -555,6 +880,7 @@ fn move_body(
                 0.0,
                 world,
                 spatial_index,
+                &[], // bodies push actors regardless of any solid actors in the way
             );
         }
         // this is ridiculous, now we have moved the actor we have to borrow the body
@@ -598,6 +924,7 @@ fn move_body(
                 vy.signum() as f32,
                 world,
                 spatial_index,
+                &[],
             );
         }
         // and again
@@ -618,11 +945,21 @@ fn move_body(
     !stopped
 }
 
-fn check_player_grounded(player_rect: &IntRect, world: &World) -> bool {
-    world.query::<&TileBody>().iter().any(|(_, c)| {
-        c.collide(
-            &feet_rect(player_rect),
-            CollisionType::TopOfBlockerOrPlatform,
-        )
-    })
+fn check_on_solid(rect: &IntRect, solids: &[SolidRect]) -> bool {
+    let feet = feet_rect(rect);
+    solids
+        .iter()
+        .any(|s| s.rect.intersects(&feet) && !s.rect.intersects(rect))
+}
+
+fn check_player_grounded(player_rect: &IntRect, world: &World, platforms: bool) -> bool {
+    let typ = if platforms {
+        CollisionType::TopOfBlockerOrPlatform
+    } else {
+        CollisionType::Blocker
+    };
+    world
+        .query::<&TileBody>()
+        .iter()
+        .any(|(_, c)| c.collide(&feet_rect(player_rect), typ))
 }
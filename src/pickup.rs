@@ -1,75 +1,159 @@
+use crate::checkpoint::CheckpointState;
+use crate::door::KeyColor;
+use crate::draw::ColorRect;
+use crate::enemy::Spawned;
+use crate::health::{Health, Victim};
+use crate::index::Indexed;
 use crate::physics::{Actor, IntRect};
 use crate::player::Controller;
-use crate::resources::SceneResources;
-use crate::weapon::{ammo_max, ammo_name, weapon_name, AmmoQuantity, AmmoType, WeaponType};
-use hecs::{CommandBuffer, World};
+use crate::powerup::PowerUpType;
+use crate::resources::{Abilities, SceneResources};
+use crate::weapon::{ammo_max, ammo_name, AmmoQuantity, AmmoType, UpgradeType, Upgrades};
+use crate::weapon_defs::WeaponType;
+use hecs::{CommandBuffer, Entity, EntityBuilder, World};
 use std::collections::HashMap;
 
+#[derive(Copy, Clone)]
 pub enum PickupType {
     Heart,
     Ammo(AmmoType, AmmoQuantity),
+    Ability(Abilities),
+    Armor(u16),
+    MaxHealth,
+    MegaHealth,
+    Key(KeyColor),
+    PowerUp(PowerUpType),
+    Upgrade(UpgradeType),
 }
 
+const MAX_ARMOR: u16 = 3;
+const HP_LIMIT: u16 = 8;
+const MEGA_HEALTH: u16 = 3;
+
 pub struct Pickup {
     pub typ: PickupType,
     touched: bool,
 }
 
-pub fn add_heart(world: &mut World, x: i32, y: i32) {
+pub fn add_health_pickup(world: &mut World, x: i32, y: i32, typ: PickupType) {
     let rect = IntRect::new(x - 8, y - 16, 16, 16);
-    let draw = crate::draw::PickupSprite::new();
-    let actor = Actor::new(&rect, 0.4);
-    world.spawn((
-        rect,
-        draw,
-        actor,
-        Pickup {
-            typ: PickupType::Heart,
-            touched: false,
-        },
-    ));
+    spawn_pickup(world, rect, typ, false);
 }
 
 pub fn add_ammo(world: &mut World, x: i32, y: i32, typ: AmmoType, amt: AmmoQuantity) {
     let rect = IntRect::new(x - 8, y - 16, 16, 16);
-    let draw = crate::draw::PickupSprite::new();
+    spawn_pickup(world, rect, PickupType::Ammo(typ, amt), false);
+}
+
+pub fn add_ability(world: &mut World, x: i32, y: i32, ability: Abilities) {
+    let rect = IntRect::new(x - 6, y - 12, 12, 12);
+    spawn_pickup(world, rect, PickupType::Ability(ability), false);
+}
+
+pub fn add_key(world: &mut World, x: i32, y: i32, key: KeyColor) {
+    let rect = IntRect::new(x - 4, y - 12, 8, 12);
+    spawn_pickup(world, rect, PickupType::Key(key), false);
+}
+
+pub fn add_powerup(world: &mut World, x: i32, y: i32, typ: PowerUpType) {
+    let rect = IntRect::new(x - 6, y - 12, 12, 12);
+    spawn_pickup(world, rect, PickupType::PowerUp(typ), false);
+}
+
+pub fn add_upgrade(world: &mut World, x: i32, y: i32, typ: UpgradeType) {
+    let rect = IntRect::new(x - 6, y - 12, 12, 12);
+    spawn_pickup(world, rect, PickupType::Upgrade(typ), false);
+}
+
+// things without their own sprite are drawn as a coloured box
+fn spawn_pickup(world: &mut World, rect: IntRect, typ: PickupType, touched: bool) {
     let actor = Actor::new(&rect, 0.4);
-    world.spawn((
-        rect,
-        draw,
-        actor,
-        Pickup {
-            typ: PickupType::Ammo(typ, amt),
-            touched: false,
-        },
-    ));
+    let mut b = EntityBuilder::new();
+    match typ {
+        PickupType::Ability(_) => b.add(ColorRect::new(macroquad::color::GOLD)),
+        PickupType::Key(key) => b.add(ColorRect::new(key.color())),
+        PickupType::PowerUp(typ) => b.add(ColorRect::new(typ.color())),
+        PickupType::Upgrade(typ) => b.add(ColorRect::new(typ.color())),
+        _ => b.add(crate::draw::PickupSprite::new()),
+    };
+    b.add_bundle((rect, actor, Pickup { typ, touched }, Indexed {}));
+    world.spawn(b.build());
+}
+
+// what a pickup was before it was taken or swapped, so it can be put back
+// if the player dies and goes back to a checkpoint
+#[derive(Clone)]
+pub enum PickupRecord {
+    Item(IntRect, PickupType),
+    Weapon(IntRect, WeaponType, Upgrades),
+}
+
+impl PickupRecord {
+    // counted towards the level's items already, so not counted again
+    pub fn restore(&self, world: &mut World) {
+        match self {
+            Self::Item(rect, typ) => spawn_pickup(world, rect.clone(), *typ, true),
+            Self::Weapon(rect, typ, upgrades) => {
+                spawn_weapon_pickup(world, rect.clone(), *typ, *upgrades, true)
+            }
+        }
+    }
+}
+
+// only the first record of each pickup is kept, as that's how it was at the checkpoint
+pub fn record_taken(checkpoint: &mut Option<CheckpointState>, id: Entity, record: PickupRecord) {
+    if let Some(cp) = checkpoint {
+        cp.taken.entry(id).or_insert(record);
+    }
+}
+
+fn ability_message(ability: Abilities) -> &'static str {
+    if ability.contains(Abilities::WALL_JUMP) {
+        "You can now jump off walls!"
+    } else if ability.contains(Abilities::DOUBLE_JUMP) {
+        "You can now jump again in mid-air!"
+    } else if ability.contains(Abilities::DASH) {
+        "Press V to dash!"
+    } else {
+        "Picked up a new ability."
+    }
 }
 
 impl Pickup {
     pub fn update(resources: &mut SceneResources, buffer: &mut CommandBuffer) -> Option<()> {
         let world = resources.world_ref.lock().unwrap();
         let mut q = world
-            .query_one::<(&IntRect, &mut Controller)>(resources.player_id)
+            .query_one::<(&IntRect, &mut Health)>(resources.player_id)
             .ok()?;
-        let (p_rect, c) = q.get()?;
-        for (id, (rect, p)) in world.query::<(&IntRect, &mut Pickup)>().iter() {
+        let (p_rect, health) = q.get()?;
+        for id in resources.dynamic_index.entities(p_rect).iter().copied() {
+            let mut pq = match world.query_one::<(&IntRect, &mut Pickup)>(id) {
+                Ok(pq) => pq,
+                Err(_) => continue,
+            };
+            let (rect, p) = match pq.get() {
+                Some(x) => x,
+                None => continue,
+            };
             if rect.intersects(p_rect) {
                 if !p.touched {
                     p.touched = true;
                     resources.stats.items += 1
                 }
+                let mut taken = false;
                 match p.typ {
                     PickupType::Heart => {
-                        if c.can_heal() {
-                            buffer.despawn(id);
-                            c.heal();
+                        if health.can_heal() {
+                            taken = true;
+                            health.heal(1);
                             resources.messages.add("Picked up a heart.".to_owned());
                         }
                     }
                     PickupType::Ammo(typ, amt) => {
                         if resources.ammo[typ] < ammo_max(typ) {
-                            buffer.despawn(id);
+                            taken = true;
                             crate::weapon::add_ammo(
+                                &resources.weapon_defs,
                                 &mut resources.weapons,
                                 &mut resources.ammo,
                                 &mut resources.selector,
@@ -81,6 +165,79 @@ impl Pickup {
                                 .add(format!("Picked up {}.", ammo_name(typ, amt)));
                         }
                     }
+                    PickupType::Armor(n) => {
+                        if health.armor < MAX_ARMOR {
+                            taken = true;
+                            health.armor = (health.armor + n).min(MAX_ARMOR);
+                            resources.messages.add("Picked up some armor.".to_owned());
+                        }
+                    }
+                    PickupType::MaxHealth => {
+                        if health.max_hp < HP_LIMIT {
+                            taken = true;
+                            health.max_hp += 1;
+                            health.heal(1);
+                            resources
+                                .messages
+                                .add("Maximum health increased!".to_owned());
+                        }
+                    }
+                    PickupType::MegaHealth => {
+                        taken = true;
+                        health.overheal(MEGA_HEALTH);
+                        resources.messages.add("Mega health!".to_owned());
+                    }
+                    PickupType::Key(key) => {
+                        taken = true;
+                        resources.keys[key] = true;
+                        resources
+                            .messages
+                            .add(format!("Picked up the {} key.", key.name()));
+                    }
+                    PickupType::PowerUp(typ) => {
+                        taken = true;
+                        resources.powerups.give(typ);
+                        resources.messages.add(format!("{}!", typ.name()));
+                    }
+                    PickupType::Upgrade(typ) => {
+                        // fitted to the selected weapon if it will take it, otherwise the next
+                        // one that will, and left lying there if none will. never the backup
+                        // laser, which is thrown away once there's ammo for anything else
+                        let fitted = resources
+                            .weapons
+                            .iter_mut()
+                            .filter(|w| w.get_type() != WeaponType::BACKUP_LASER)
+                            .find_map(|w| {
+                                if w.upgrade(typ) {
+                                    Some(w.get_type())
+                                } else {
+                                    None
+                                }
+                            });
+                        if let Some(w) = fitted {
+                            taken = true;
+                            resources.messages.add(format!(
+                                "Upgraded the {} with {}!",
+                                resources.weapon_defs.get(w).name,
+                                typ.name()
+                            ));
+                        }
+                    }
+                    PickupType::Ability(ability) => {
+                        taken = true;
+                        if !resources.abilities.contains(ability) {
+                            resources.abilities.insert(ability);
+                            resources.messages.add(ability_message(ability).to_owned());
+                        }
+                    }
+                }
+                if taken {
+                    buffer.despawn(id);
+                    record_taken(
+                        &mut resources.checkpoint,
+                        id,
+                        PickupRecord::Item(rect.clone(), p.typ),
+                    );
                 }
             }
         }
@@ -91,18 +248,32 @@ impl Pickup {
 pub struct WeaponPickup {
     touched: bool,
     pub typ: WeaponType,
+    // kept when a weapon is swapped out, so it can be picked up again as it was
+    pub upgrades: Upgrades,
 }
 
 pub fn add_weapon(world: &mut World, x: i32, y: i32, typ: WeaponType) {
     let rect = IntRect::new(x - 12, y - 16, 24, 16);
+    spawn_weapon_pickup(world, rect, typ, Upgrades::default(), false);
+}
+
+fn spawn_weapon_pickup(
+    world: &mut World,
+    rect: IntRect,
+    typ: WeaponType,
+    upgrades: Upgrades,
+    touched: bool,
+) {
     let actor = Actor::new(&rect, 0.4);
     world.spawn((
         rect,
         actor,
         WeaponPickup {
-            touched: false,
+            touched,
             typ,
+            upgrades,
         },
+        Indexed {},
     ));
 }
 
@@ -114,7 +285,15 @@ impl WeaponPickup {
             .query_one::<(&IntRect, &mut Controller)>(resources.player_id)
             .ok()?;
         let (p_rect, c) = q.get()?;
-        for (id, (rect, p)) in world.query::<(&IntRect, &mut WeaponPickup)>().iter() {
+        for id in resources.dynamic_index.entities(p_rect).iter().copied() {
+            let mut pq = match world.query_one::<(&IntRect, &mut WeaponPickup)>(id) {
+                Ok(pq) => pq,
+                Err(_) => continue,
+            };
+            let (rect, p) = match pq.get() {
+                Some(x) => x,
+                None => continue,
+            };
             if rect.intersects(p_rect) {
                 if !p.touched {
                     p.touched = true;
@@ -124,19 +303,20 @@ impl WeaponPickup {
             }
         }
         for typ in new_touched.keys() {
+            let name = &resources.weapon_defs.get(*typ).name;
             if !c.touched_weapons.contains_key(typ) {
                 if resources.weapons.iter().any(|w| w.get_type() == *typ) {
                     resources
                         .messages
-                        .add(format!("Already carrying {}.", weapon_name(*typ)));
-                } else if resources.weapons.len() < 3 {
+                        .add(format!("Already carrying {}.", name));
+                } else if resources.weapons.len() < resources.weapon_defs.max_weapons {
                     resources
                         .messages
-                        .add(format!("Press C to pick up {}.", weapon_name(*typ)));
+                        .add(format!("Press C to pick up {}.", name));
                 } else {
                     resources
                         .messages
-                        .add(format!("Press C to swap for {}.", weapon_name(*typ)));
+                        .add(format!("Press C to swap for {}.", name));
                 }
             }
         }
@@ -144,3 +324,33 @@ impl WeaponPickup {
         Some(())
     }
 }
+
+const LOOT_CHANCE: f32 = 0.2;
+
+// enemies sometimes leave a heart behind when they die
+pub fn drop_loot(resources: &mut SceneResources, buffer: &mut CommandBuffer) {
+    let world = resources.world_ref.lock().unwrap();
+    for d in &resources.deaths {
+        if let Victim::Enemy(_) = d.victim {
+            // or a spawner would be an endless supply of hearts
+            if world.satisfies::<&Spawned>(d.id).unwrap_or(false) {
+                continue;
+            }
+            if quad_rand::gen_range(0.0, 1.0) < LOOT_CHANCE {
+                let rect = IntRect::new(d.x - 8, d.y - 8, 16, 16);
+                let actor = Actor::new(&rect, 0.4);
+                buffer.spawn((
+                    rect,
+                    crate::draw::PickupSprite::new(),
+                    actor,
+                    Pickup {
+                        typ: PickupType::Heart,
+                        // dropped items don't count towards the level's item total
+                        touched: true,
+                    },
+                    Indexed {},
+                ));
+            }
+        }
+    }
+}
@@ -1,45 +1,105 @@
-use crate::draw::PlayerSprite;
+use crate::draw::{PlayerSprite, PLAYER_SPIN_FRAMES};
+use crate::health::{Damage, DamageType, Health, Victim};
+use crate::index::{Indexed, SpatialIndex};
 use crate::input::{Input, KeyState, VirtualKey};
-use crate::physics::{Actor, IntRect, Secrecy, TriggerZone};
-use crate::pickup::WeaponPickup;
-use crate::resources::SceneResources;
+use crate::melee::{swing, MELEE_COOLDOWN, MELEE_FRAMES};
+use crate::physics::{
+    collide_any, feet_rect, Actor, Heavy, Impulse, IntRect, Secrecy, TriggerZone,
+};
+use crate::pickup::{record_taken, PickupRecord, WeaponPickup};
+use crate::powerup::{PowerUpType, SPEED_MULTIPLIER};
+use crate::resources::{Abilities, Inventory, SceneResources};
 use crate::switch::Switch;
 use crate::vfx::create_explosion;
-use crate::weapon::{new_weapon, select_fireable_weapon, weapon_name_indef, WeaponType};
-use hecs::{CommandBuffer, Entity};
+use crate::weapon::{select_fireable_weapon, upgraded_weapon, Shot, Triggers};
+use crate::weapon_defs::WeaponType;
+use hecs::{CommandBuffer, Entity, World};
 use std::collections::{HashMap, HashSet};
 
+pub const PLAYER_MAX_HP: u16 = 3;
+const STAND_HEIGHT: i32 = 24;
+const CROUCH_HEIGHT: i32 = 14;
+const DROP_FRAMES: u8 = 8;
+const STUN_FRAMES: u8 = 8;
+
+pub fn add_player(world: &mut World, x: i32, y: i32, inv: &Inventory) -> Entity {
+    let rect = IntRect::new(x - 8, y - 24, 14, 24);
+    let actor = Actor::new(&rect, 0.6);
+    let mut health = Health::new(inv.max_hp);
+    health.armor = inv.armor;
+    world.spawn((
+        rect,
+        actor,
+        Controller::new(),
+        PlayerSprite::new(),
+        health,
+        Heavy {},
+        Indexed {},
+    ))
+}
+
 pub struct Controller {
     jump_frames: u32,
+    // ignore left/right for a little while after a wall jump so the kick can carry us away
+    wall_jump_frames: u8,
+    // frames left in which we can still jump after leaving the ground
+    coyote_frames: u8,
+    // frames left in which an early jump press will still be acted on
+    jump_buffer: u8,
+    double_jumped: bool,
+    crouching: bool,
+    dash_frames: u8,
+    dash_cooldown: u8,
     zones: HashSet<String>,
     pub touched_weapons: HashMap<WeaponType, Entity>,
     facing: i8,
     fire_timer: u32,
     hurt_timer: u8,
-    pub hp: u8,
-    god_mode: bool,
+    // knockback from the last hit, waiting to be applied to our velocity
+    knockback: Option<Impulse>,
+    stun_frames: u8,
+    melee_cooldown: u8,
 }
 
 impl Controller {
     pub fn new() -> Self {
         Self {
             jump_frames: 0,
+            wall_jump_frames: 0,
+            coyote_frames: 0,
+            jump_buffer: 0,
+            double_jumped: false,
+            crouching: false,
+            dash_frames: 0,
+            dash_cooldown: 0,
             zones: HashSet::new(),
             touched_weapons: HashMap::new(),
             facing: 1,
             fire_timer: 100000,
             hurt_timer: 0,
-            hp: 3,
-            god_mode: false,
+            knockback: None,
+            stun_frames: 0,
+            melee_cooldown: 0,
         }
     }
 
     pub fn update(resources: &mut SceneResources, buffer: &mut CommandBuffer, input: &Input) {
         let world = resources.world_ref.lock().unwrap();
-        let mut q = world.query::<(&mut Actor, &IntRect, &mut PlayerSprite, &mut Controller)>();
-        for (id, (player, p_rect, sprite, controller)) in q.iter() {
+        let mut q = world.query::<(&mut Actor, &mut IntRect, &mut PlayerSprite, &mut Controller)>();
+        for (p_id, (player, p_rect, sprite, controller)) in q.iter() {
+            let m = &resources.movement;
+            player.drag = m.drag;
             let mut new_zones: HashSet<String> = HashSet::new();
-            for (_, (trigger, t_rect)) in world.query::<(&mut TriggerZone, &IntRect)>().iter() {
+            let nearby = resources.dynamic_index.entities(p_rect);
+            for t_id in nearby.iter() {
+                let mut tq = match world.query_one::<(&mut TriggerZone, &IntRect)>(*t_id) {
+                    Ok(tq) => tq,
+                    Err(_) => continue,
+                };
+                let (trigger, t_rect) = match tq.get() {
+                    Some(x) => x,
+                    None => continue,
+                };
                 if p_rect.intersects(t_rect) {
                     if !controller.zones.contains(&trigger.name) {
                         resources
@@ -60,20 +120,74 @@ impl Controller {
                 }
             }
             controller.zones = new_zones;
-            if input.is_down(VirtualKey::Left) {
-                player.vx -= 3.0;
-                controller.facing = -1;
-                sprite.flipped = false;
+            if let Some(k) = controller.knockback.take() {
+                k.apply(player, p_rect);
+                controller.dash_frames = 0;
+                controller.jump_frames = 0;
+            }
+            let stunned = controller.stun_frames > 0;
+            controller.stun_frames = controller.stun_frames.saturating_sub(1);
+            controller.dash_cooldown = controller.dash_cooldown.saturating_sub(1);
+            if input.is_pressed(VirtualKey::Dash)
+                && !stunned
+                && controller.dash_cooldown == 0
+                && resources.has_ability(Abilities::DASH)
+            {
+                controller.dash_frames = m.dash_frames;
+                controller.dash_cooldown = m.dash_cooldown_frames;
+                controller.wall_jump_frames = 0;
             }
-            if input.is_down(VirtualKey::Right) {
-                player.vx += 3.0;
-                controller.facing = 1;
-                sprite.flipped = true;
+            if controller.dash_frames > 0 {
+                controller.dash_frames -= 1;
+                player.vx = controller.facing as f32 * m.dash_speed;
+                // cancel out gravity so the dash stays level
+                player.vy = -m.gravity;
+            } else if stunned {
+                // no control while reeling from a hit
+            } else if controller.wall_jump_frames > 0 {
+                controller.wall_jump_frames -= 1;
+            } else {
+                let accel = if resources.powerups.is_active(PowerUpType::Speed) {
+                    m.accel * SPEED_MULTIPLIER
+                } else {
+                    m.accel
+                };
+                if input.is_down(VirtualKey::Left) {
+                    player.vx -= accel;
+                    controller.facing = -1;
+                    sprite.flipped = false;
+                }
+                if input.is_down(VirtualKey::Right) {
+                    player.vx += accel;
+                    controller.facing = 1;
+                    sprite.flipped = true;
+                }
+            }
+            let want_crouch = input.is_down(VirtualKey::Down) && controller.dash_frames == 0;
+            if want_crouch && !controller.crouching {
+                player.set_height(p_rect, CROUCH_HEIGHT);
+                controller.crouching = true;
+            } else if !want_crouch && controller.crouching {
+                // only stand back up if there's room overhead
+                let mut standing = p_rect.clone();
+                standing.y -= STAND_HEIGHT - standing.h;
+                standing.h = STAND_HEIGHT;
+                if !collide_any(&world, &resources.body_index, &standing) {
+                    player.set_height(p_rect, STAND_HEIGHT);
+                    controller.crouching = false;
+                }
             }
             if input.is_pressed(VirtualKey::Interact) {
                 let mut interacted = false;
-                let mut q = world.query::<(&Actor, &IntRect, &mut Switch)>();
-                for (_, (_, s_rect, s)) in q.iter() {
+                for s_id in nearby.iter() {
+                    let mut sq = match world.query_one::<(&Actor, &IntRect, &mut Switch)>(*s_id) {
+                        Ok(sq) => sq,
+                        Err(_) => continue,
+                    };
+                    let (_, s_rect, s) = match sq.get() {
+                        Some(x) => x,
+                        None => continue,
+                    };
                     if p_rect.intersects(s_rect) && s.enabled {
                         resources
                             .triggers
@@ -98,26 +212,36 @@ impl Controller {
                             if let Some(n) = resources
                                 .weapons
                                 .iter()
-                                .position(|w| w.get_type() == WeaponType::BackupLaser)
+                                .position(|w| w.get_type() == WeaponType::BACKUP_LASER)
                             {
                                 resources.weapons.remove(n);
                             }
                             // now we can't have the backup laser so we can just use the len()
                             // to work out if the inventory is full
-                            if resources.weapons.len() < 3 {
+                            let mut w = world.get::<&mut WeaponPickup>(id).unwrap();
+                            let rect = world.get::<&IntRect>(id).unwrap().clone();
+                            record_taken(
+                                &mut resources.checkpoint,
+                                id,
+                                PickupRecord::Weapon(rect, w.typ, w.upgrades),
+                            );
+                            let weapon = upgraded_weapon(&resources.weapon_defs, typ, w.upgrades);
+                            if resources.weapons.len() < resources.weapon_defs.max_weapons {
                                 buffer.despawn(id);
-                                resources.weapons.push_front(new_weapon(typ));
+                                resources.weapons.push_front(weapon);
                             } else {
-                                let mut w = world.get::<&mut WeaponPickup>(id).unwrap();
-                                w.typ = resources.weapons[0].get_type();
-                                resources.weapons.pop_front();
-                                resources.weapons.push_front(new_weapon(typ));
+                                // leave the old weapon where the new one was
+                                let old = resources.weapons.pop_front().unwrap();
+                                w.typ = old.get_type();
+                                w.upgrades = old.get_upgrades();
+                                resources.weapons.push_front(weapon);
                                 // mark it as touched to suppress the message next frame
                                 controller.touched_weapons.insert(typ, id);
                             }
-                            resources
-                                .messages
-                                .add(format!("Picked up {}.", weapon_name_indef(typ)));
+                            resources.messages.add(format!(
+                                "Picked up {}.",
+                                resources.weapon_defs.get(typ).name_indef
+                            ));
                             resources.selector.change(0.0);
                         }
                         // either not touching a weapon pickup or it's one we already have
@@ -125,21 +249,94 @@ impl Controller {
                     }
                 }
             }
-            if player.grounded && input.is_pressed(VirtualKey::Jump) {
-                player.vy = -6.0;
-                controller.jump_frames = 5;
+            let wall_dir = if player.grounded || !resources.has_ability(Abilities::WALL_JUMP) {
+                0
+            } else {
+                wall_contact(&world, &resources.body_index, p_rect)
+            };
+            if player.grounded || wall_dir != 0 {
+                controller.double_jumped = false;
+            }
+            if player.grounded {
+                controller.coyote_frames = m.coyote_frames;
+            } else {
+                controller.coyote_frames = controller.coyote_frames.saturating_sub(1);
+            }
+            if input.is_pressed(VirtualKey::Jump) {
+                controller.jump_buffer = m.jump_buffer_frames;
+            } else {
+                controller.jump_buffer = controller.jump_buffer.saturating_sub(1);
+            }
+            if stunned {
+                controller.jump_buffer = 0;
+            }
+            if controller.crouching
+                && player.grounded
+                && controller.jump_buffer > 0
+                && !collide_any(&world, &resources.body_index, &feet_rect(p_rect))
+            {
+                // standing on a platform rather than solid ground, so drop through it
+                player.drop_frames = DROP_FRAMES;
+                controller.jump_buffer = 0;
+                controller.coyote_frames = 0;
+            } else if controller.jump_buffer > 0 && controller.coyote_frames > 0 {
+                player.vy = m.jump_velocity;
+                controller.jump_frames = m.jump_frames;
+                controller.jump_buffer = 0;
+                controller.coyote_frames = 0;
+            } else if controller.jump_buffer > 0 && wall_dir != 0 {
+                // kick off away from the wall
+                player.vy = m.jump_velocity;
+                player.vx = -wall_dir as f32 * m.wall_jump_kick;
+                controller.jump_frames = m.jump_frames;
+                controller.jump_buffer = 0;
+                controller.wall_jump_frames = m.wall_jump_lock_frames;
+                controller.facing = -wall_dir;
+                sprite.flipped = wall_dir < 0;
+            } else if input.is_pressed(VirtualKey::Jump)
+                && !controller.double_jumped
+                && resources.has_ability(Abilities::DOUBLE_JUMP)
+            {
+                player.vy = m.jump_velocity;
+                controller.jump_frames = m.jump_frames;
+                controller.jump_buffer = 0;
+                controller.double_jumped = true;
+                controller.dash_frames = 0;
+                sprite.spin = PLAYER_SPIN_FRAMES;
             } else if controller.jump_frames > 0
                 && input.is_down(VirtualKey::Jump)
                 && player.vy < 0.0
             {
-                player.vy = -10.0;
+                player.vy = m.jump_hold_velocity;
                 controller.jump_frames -= 1;
             } else {
                 controller.jump_frames = 0;
             }
+            let pushing_wall = (wall_dir < 0 && input.is_down(VirtualKey::Left))
+                || (wall_dir > 0 && input.is_down(VirtualKey::Right));
+            if pushing_wall && player.vy > m.wall_slide_speed {
+                player.vy = m.wall_slide_speed;
+            }
             if player.grounded {
                 sprite.n += player.vx.abs() as i32;
             }
+            sprite.dashing = controller.dash_frames > 0;
+            sprite.spin = sprite.spin.saturating_sub(1);
+            sprite.swing = sprite.swing.saturating_sub(1);
+            controller.melee_cooldown = controller.melee_cooldown.saturating_sub(1);
+            if input.is_pressed(VirtualKey::Melee) && !stunned && controller.melee_cooldown == 0 {
+                controller.melee_cooldown = MELEE_COOLDOWN;
+                sprite.swing = MELEE_FRAMES;
+                swing(
+                    &world,
+                    &resources.dynamic_index,
+                    buffer,
+                    p_id,
+                    p_rect,
+                    controller.facing,
+                    resources.powerups.damage_multiplier(),
+                );
+            }
             if input.is_pressed(VirtualKey::PrevWeapon) {
                 if resources.weapons.len() > 1 {
                     resources.weapons.rotate_left(1);
@@ -160,23 +357,38 @@ impl Controller {
             let w = &mut resources.weapons[0];
             let t = w.get_ammo_type();
             let n = w.get_ammo_use();
-            if resources.ammo[t] >= n {
-                // can fire current weapon, up to the weapon to say if we should
-                if w.update(buffer, player, p_rect, controller.facing, fks) {
-                    controller.fire_timer = 0;
-                    sprite.firing = true;
-                    resources.ammo[t] -= n;
-                }
-            } else {
-                // can't fire current weapon, try changing if player pressed fire
-                if fks == KeyState::Pressed {
-                    select_fireable_weapon(
-                        &mut resources.weapons,
-                        &mut resources.ammo,
-                        &mut resources.selector,
-                    );
+            let alt_n = w.get_alt_ammo_use();
+            let infinite = resources.powerups.is_active(PowerUpType::InfiniteAmmo);
+            let can_fire = resources.ammo[t] >= n || infinite;
+            let can_alt_fire = resources.ammo[t] >= alt_n || infinite;
+            // up to the weapon to say if it fires, as long as there's ammo for it
+            let triggers = Triggers {
+                fire: if can_fire { fks } else { KeyState::NotPressed },
+                alt_fire: input.state(VirtualKey::AltFire),
+                can_alt_fire,
+            };
+            let damage = resources.powerups.damage_multiplier();
+            if let Some(shot) =
+                w.update(buffer, player, p_rect, controller.facing, triggers, damage)
+            {
+                controller.fire_timer = 0;
+                sprite.firing = true;
+                if !infinite && !w.saves_ammo() {
+                    resources.ammo[t] = resources.ammo[t].saturating_sub(match shot {
+                        Shot::Primary => n,
+                        Shot::Alt => alt_n,
+                    });
                 }
             }
+            if !can_fire && fks == KeyState::Pressed {
+                // can't fire current weapon, try changing
+                select_fireable_weapon(
+                    &resources.weapon_defs,
+                    &mut resources.weapons,
+                    &mut resources.ammo,
+                    &mut resources.selector,
+                );
+            }
             controller.fire_timer += 1;
             sprite.muzzle_flash = controller.fire_timer.min(100) as u8;
             if controller.fire_timer > 5 {
@@ -188,44 +400,76 @@ impl Controller {
             } else {
                 sprite.blink = false;
             }
-            if controller.hp == 0 || (player.crushed && !controller.god_mode) {
-                buffer.remove_one::<PlayerSprite>(id);
-                buffer.remove_one::<Controller>(id);
-                let (px, py) = p_rect.centre_int();
-                create_explosion(buffer, px, py);
-                resources.messages.add("You have died.".to_owned());
-            }
             #[cfg(debug_assertions)]
             {
                 use macroquad::prelude::{is_key_down, KeyCode};
-                if is_key_down(KeyCode::Q) && is_key_down(KeyCode::D) && !controller.god_mode {
-                    controller.god_mode = true;
+                if is_key_down(KeyCode::Q)
+                    && is_key_down(KeyCode::D)
+                    && !resources.powerups.is_permanent(PowerUpType::Invulnerable)
+                {
+                    // god mode is just invulnerability that never runs out
+                    resources.powerups.give_permanent(PowerUpType::Invulnerable);
                     resources.messages.add("God mode enabled!".to_owned());
                 }
             }
         }
     }
 
+    pub fn is_dashing(&self) -> bool {
+        self.dash_frames > 0
+    }
+
     pub fn can_hurt(&self) -> bool {
-        self.hurt_timer == 0 && self.hp > 0
+        self.hurt_timer == 0
     }
 
-    pub fn hurt(&mut self) {
-        if self.hurt_timer == 0 && self.hp > 0 && !self.god_mode {
-            self.hp -= 1;
+    pub fn hurt(&mut self, health: &mut Health, dmg: Damage, knockback: Impulse) {
+        // invulnerability also shrugs off the knockback
+        if self.can_hurt() && health.is_alive() && !health.invulnerable {
+            health.damage(dmg);
             self.hurt_timer = 24;
+            self.knockback = Some(knockback);
+            self.stun_frames = STUN_FRAMES;
         }
     }
 
+    // thrown by an explosion, whether or not it did any damage
+    pub fn blast(&mut self, knockback: Impulse) {
+        self.knockback = Some(knockback);
+    }
+
     pub fn was_hurt(&self) -> bool {
         self.hurt_timer >= 23
     }
+}
 
-    pub fn can_heal(&self) -> bool {
-        self.hp < 3
+pub fn handle_player_death(resources: &mut SceneResources, buffer: &mut CommandBuffer) {
+    for d in &resources.deaths {
+        if d.victim != Victim::Player {
+            continue;
+        }
+        buffer.remove_one::<PlayerSprite>(d.id);
+        buffer.remove_one::<Controller>(d.id);
+        create_explosion(buffer, d.x, d.y);
+        let msg = match d.cause {
+            DamageType::Crush => "You were crushed.",
+            DamageType::Explosive => "You were blown up.",
+            DamageType::Fire => "You were burned to a crisp.",
+            DamageType::Laser => "You have died.",
+            DamageType::Melee => "You were beaten to death.",
+            DamageType::Impact => "You were battered to death.",
+        };
+        resources.messages.add(msg.to_owned());
     }
+}
 
-    pub fn heal(&mut self) {
-        self.hp += 1;
+// which side of the rect is up against a wall, if any
+fn wall_contact(world: &World, body_index: &SpatialIndex, rect: &IntRect) -> i8 {
+    for dir in [-1, 1] {
+        let r = IntRect::new(rect.x + dir as i32, rect.y, rect.w, rect.h);
+        if collide_any(world, body_index, &r) {
+            return dir;
+        }
     }
+    0
 }
@@ -0,0 +1,126 @@
+use crate::health::Health;
+use crate::resources::SceneResources;
+use enum_map::{Enum, EnumMap};
+use macroquad::color::{Color, GREEN, MAGENTA, ORANGE, SKYBLUE};
+
+// in frames
+const POWERUP_DURATION: u32 = 20 * 30;
+// picking up another of the same kind adds to the time left, but only up to this
+const MAX_POWERUP_TIME: u32 = 2 * POWERUP_DURATION;
+// never counts down, e.g. for god mode
+const PERMANENT: u32 = u32::MAX;
+
+pub const DAMAGE_MULTIPLIER: u16 = 3;
+pub const SPEED_MULTIPLIER: f32 = 1.5;
+
+#[derive(enum_iterator::Sequence, Enum, Copy, Clone, PartialEq, Eq)]
+pub enum PowerUpType {
+    Damage,
+    Invulnerable,
+    Speed,
+    InfiniteAmmo,
+}
+
+impl PowerUpType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Damage => "Triple damage",
+            Self::Invulnerable => "Invulnerability",
+            Self::Speed => "Speed boost",
+            Self::InfiniteAmmo => "Infinite ammo",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Damage => "DMG",
+            Self::Invulnerable => "INV",
+            Self::Speed => "SPD",
+            Self::InfiniteAmmo => "AMO",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Self::Damage => ORANGE,
+            Self::Invulnerable => MAGENTA,
+            Self::Speed => SKYBLUE,
+            Self::InfiniteAmmo => GREEN,
+        }
+    }
+}
+
+// different kinds of power-up all run at once, each with its own timer
+pub struct PowerUps {
+    timers: EnumMap<PowerUpType, u32>,
+}
+
+impl PowerUps {
+    pub fn new() -> Self {
+        Self {
+            timers: EnumMap::default(),
+        }
+    }
+
+    pub fn give(&mut self, typ: PowerUpType) {
+        let t = &mut self.timers[typ];
+        if *t != PERMANENT {
+            *t = (*t + POWERUP_DURATION).min(MAX_POWERUP_TIME);
+        }
+    }
+
+    pub fn give_permanent(&mut self, typ: PowerUpType) {
+        self.timers[typ] = PERMANENT;
+    }
+
+    // timed power-ups don't survive death, though god mode does
+    pub fn clear_timed(&mut self) {
+        for (_, t) in self.timers.iter_mut() {
+            if *t != PERMANENT {
+                *t = 0;
+            }
+        }
+    }
+
+    pub fn is_active(&self, typ: PowerUpType) -> bool {
+        self.timers[typ] > 0
+    }
+
+    pub fn is_permanent(&self, typ: PowerUpType) -> bool {
+        self.timers[typ] == PERMANENT
+    }
+
+    // seconds left, rounded up, or None if it isn't running out
+    pub fn seconds_left(&self, typ: PowerUpType) -> Option<u32> {
+        match self.timers[typ] {
+            0 | PERMANENT => None,
+            t => Some((t + 29) / 30),
+        }
+    }
+
+    pub fn damage_multiplier(&self) -> u16 {
+        if self.is_active(PowerUpType::Damage) {
+            DAMAGE_MULTIPLIER
+        } else {
+            1
+        }
+    }
+
+    pub fn update(resources: &mut SceneResources) {
+        let powerups = &mut resources.powerups;
+        for (typ, t) in powerups.timers.iter_mut() {
+            if *t > 0 && *t != PERMANENT {
+                *t -= 1;
+                if *t == 0 {
+                    resources
+                        .messages
+                        .add(format!("{} has worn off.", typ.name()).to_owned());
+                }
+            }
+        }
+        let world = resources.world_ref.lock().unwrap();
+        if let Ok(mut health) = world.get::<&mut Health>(resources.player_id) {
+            health.invulnerable = powerups.is_active(PowerUpType::Invulnerable);
+        }
+    }
+}
@@ -1,13 +1,18 @@
+use crate::breakable::damage_tiles;
 use crate::draw::ZapSprite;
 use crate::enemy::EnemyHittable;
-use crate::physics::collide_any;
-use crate::physics::IntRect;
+use crate::health::{Damage, DamageType, Health};
+use crate::index::{DynamicIndex, Indexed};
+use crate::physics::{sweep_area, sweep_rect, sweep_tiles, Impulse, IntRect};
 use crate::player::Controller;
 use crate::resources::SceneResources;
+use crate::vfx::create_explosion;
+use crate::vfx::BeamEffect;
 use crate::vfx::Explosion;
 use crate::vfx::FireballEffect;
 use crate::vfx::ZapFlash;
-use hecs::{CommandBuffer, World};
+use hecs::{CommandBuffer, Entity, World};
+use macroquad::math::{vec2, Vec2};
 
 pub struct DamageEnemies {}
 pub struct DamagePlayer {}
@@ -15,6 +20,79 @@ pub struct LaserImpact {}
 pub struct FireballSplit {}
 pub struct ProjectileGravity {}
 pub struct ProjectileDrag {}
+// how hard whatever this projectile hits gets knocked back
+pub struct Knockback(pub f32);
+// blows up on impact, hurting everything nearby including the player
+#[derive(Copy, Clone)]
+pub struct Splash {
+    pub radius: f32,
+    pub force: f32,
+}
+
+// passes through this many enemies before stopping, hitting each of them only once
+pub struct Pierce {
+    remaining: u8,
+    hit: Vec<Entity>,
+}
+
+impl Pierce {
+    pub fn new(remaining: u8) -> Self {
+        Self {
+            remaining,
+            hit: Vec::new(),
+        }
+    }
+
+    fn has_hit(&self, id: Entity) -> bool {
+        self.hit.contains(&id)
+    }
+
+    // returns true if the projectile carries on through the enemy
+    fn pierce(&mut self, id: Entity) -> bool {
+        self.hit.push(id);
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// ricochets off walls this many times before stopping like anything else would.
+// restitution is how much of the speed into the wall is kept
+pub struct Bounce {
+    remaining: u8,
+    restitution: f32,
+}
+
+impl Bounce {
+    pub fn new(remaining: u8, restitution: f32) -> Self {
+        Self {
+            remaining,
+            restitution,
+        }
+    }
+}
+
+// hits everything along a horizontal line at once, instead of travelling.
+// it's dealt with the frame after it is fired, then despawned
+pub struct Beam {
+    pub x: f32,
+    pub y: f32,
+    pub dir: i8,
+    pub range: f32,
+}
+
+// the player gets off lightly from their own explosions so rocket jumping is worth it
+const SELF_SPLASH_SCALE: f32 = 0.5;
+
+#[derive(PartialEq, Eq)]
+enum Impact {
+    Wall,
+    Enemy(Entity),
+    Player,
+}
 
 pub struct Projectile {
     prec_x: f32,
@@ -35,76 +113,158 @@ impl Projectile {
 
     pub fn update(resources: &mut SceneResources, buffer: &mut CommandBuffer) {
         let world = resources.world_ref.lock().unwrap();
+        let mut tile_hits = Vec::new();
+        let mut explosions = Vec::new();
+        let player_rect = world
+            .get::<&IntRect>(resources.player_id)
+            .ok()
+            .map(|r| r.clone());
         for (e, (proj, rect)) in world.query::<(&mut Projectile, &mut IntRect)>().iter() {
-            let ox = rect.x;
-            let oy = rect.y;
-            proj.prec_x += proj.vx;
-            proj.prec_y += proj.vy;
-            rect.x = proj.prec_x.round() as i32;
-            rect.y = proj.prec_y.round() as i32;
-            if collide_any(&world, &resources.body_index, rect) {
-                buffer.despawn(e);
-                if world.satisfies::<&LaserImpact>(e).unwrap_or(false) {
-                    let (x, y) = find_collision_pos(&world, resources, ox, oy, rect);
-                    let sx = if proj.vx > 0.0 { x + rect.w - 1 } else { x };
-                    buffer.spawn((ZapFlash::new_from_centre(sx, y + 2),));
-                }
-                if world.satisfies::<&FireballSplit>(e).unwrap_or(false) {
-                    let (x, y) = find_collision_pos(&world, resources, ox, oy, rect);
-                    spawn_mini_fireballs(buffer, x + 8, y + 8);
-                }
+            // find the first thing this projectile would run into during this frame
+            let mut impact = None;
+            let mut best = sweep_tiles(&world, &resources.body_index, rect, proj.vx, proj.vy);
+            if best.is_some() {
+                impact = Some(Impact::Wall);
             }
-        }
-        for (e, (proj, rect, _)) in world
-            .query::<(&mut Projectile, &mut IntRect, &DamageEnemies)>()
-            .iter()
-        {
-            let mut live = true;
-            for (_, (en, e_rect)) in world.query::<(&mut EnemyHittable, &IntRect)>().iter() {
-                if live && en.hp > 0 && rect.intersects(e_rect) {
-                    buffer.despawn(e);
-                    if world.satisfies::<&LaserImpact>(e).unwrap_or(false) {
-                        let sx = if proj.vx > 0.0 {
-                            rect.x + rect.w - 1
-                        } else {
-                            rect.x
-                        };
-                        buffer.spawn((ZapFlash::new_from_centre(sx, rect.y + 2),));
+            if world.satisfies::<&DamageEnemies>(e).unwrap_or(false) {
+                let area = sweep_area(rect, proj.vx, proj.vy);
+                for en_id in resources.dynamic_index.entities(&area).iter() {
+                    if world.get::<&Pierce>(e).map_or(false, |p| p.has_hit(*en_id)) {
+                        continue;
                     }
-                    if world.satisfies::<&FireballSplit>(e).unwrap_or(false) {
-                        spawn_mini_fireballs(buffer, rect.x + 8, rect.y + 8);
+                    let mut q = match world.query_one::<(&EnemyHittable, &Health, &IntRect)>(*en_id)
+                    {
+                        Ok(q) => q,
+                        Err(_) => continue,
+                    };
+                    if let Some((_, health, e_rect)) = q.get() {
+                        if health.is_alive() {
+                            if let Some(hit) = sweep_rect(rect, proj.vx, proj.vy, e_rect) {
+                                if best.as_ref().map_or(true, |b| hit.t < b.t) {
+                                    best = Some(hit);
+                                    impact = Some(Impact::Enemy(*en_id));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            let can_hurt_player = world
+                .get::<&Controller>(resources.player_id)
+                .map_or(false, |c| c.can_hurt())
+                && world
+                    .get::<&Health>(resources.player_id)
+                    .map_or(false, |h| h.is_alive());
+            if can_hurt_player && world.satisfies::<&DamagePlayer>(e).unwrap_or(false) {
+                if let Some(p_rect) = &player_rect {
+                    if let Some(hit) = sweep_rect(rect, proj.vx, proj.vy, p_rect) {
+                        if best.as_ref().map_or(true, |b| hit.t < b.t) {
+                            best = Some(hit);
+                            impact = Some(Impact::Player);
+                        }
                     }
-                    en.hurt(1);
-                    live = false;
                 }
             }
-        }
 
-        if let Ok(mut q) = world.query_one::<(&mut Controller, &IntRect)>(resources.player_id) {
-            if let Some((c, p_rect)) = q.get() {
-                if c.can_hurt() {
-                    for (id, (proj, rect, _)) in world
-                        .query::<(&mut Projectile, &mut IntRect, &DamagePlayer)>()
-                        .iter()
-                    {
-                        if rect.intersects(p_rect) {
-                            c.hurt();
-                            buffer.despawn(id);
-                            if world.satisfies::<&LaserImpact>(id).unwrap_or(false) {
-                                let sx = if proj.vx > 0.0 {
-                                    rect.x + rect.w - 1
-                                } else {
-                                    rect.x
-                                };
-                                buffer.spawn((ZapFlash::new_from_centre(sx, rect.y + 2),));
-                            }
-                            if world.satisfies::<&FireballSplit>(id).unwrap_or(false) {
-                                spawn_mini_fireballs(buffer, rect.x + 8, rect.y + 8);
-                            }
+            let t = best.as_ref().map_or(1.0, |b| b.t);
+            proj.prec_x += proj.vx * t;
+            proj.prec_y += proj.vy * t;
+            rect.x = proj.prec_x.round() as i32;
+            rect.y = proj.prec_y.round() as i32;
+            let (impact, hit) = match (impact, best) {
+                (Some(impact), Some(hit)) => (impact, hit),
+                _ => continue,
+            };
+
+            if impact == Impact::Wall {
+                if let Ok(mut bounce) = world.get::<&mut Bounce>(e) {
+                    if bounce.remaining > 0 {
+                        bounce.remaining -= 1;
+                        // reflect off the surface, losing some speed into it
+                        let v = vec2(proj.vx, proj.vy);
+                        let v = v - (1.0 + bounce.restitution) * v.dot(hit.normal) * hit.normal;
+                        proj.vx = v.x;
+                        proj.vy = v.y;
+                        if world.satisfies::<&LaserImpact>(e).unwrap_or(false) {
+                            let (ix, iy) = hit.contact_point(rect);
+                            buffer.spawn((ZapFlash::new_from_centre(ix, iy),));
                         }
+                        continue;
                     }
                 }
+            }
+            let pierced = match impact {
+                Impact::Enemy(id) => world
+                    .get::<&mut Pierce>(e)
+                    .map_or(false, |mut p| p.pierce(id)),
+                _ => false,
             };
+            if !pierced {
+                buffer.despawn(e);
+            }
+            let (ix, iy) = hit.contact_point(rect);
+            if world.satisfies::<&LaserImpact>(e).unwrap_or(false) {
+                buffer.spawn((ZapFlash::new_from_centre(ix, iy),));
+            }
+            if world.satisfies::<&FireballSplit>(e).unwrap_or(false) {
+                let (cx, cy) = rect.centre_int();
+                spawn_mini_fireballs(buffer, cx, cy);
+                if impact == Impact::Wall {
+                    tile_hits.push(IntRect::new(cx - 12, cy - 12, 24, 24));
+                }
+            }
+            let dmg = world
+                .get::<&Damage>(e)
+                .map_or(Damage::new(1, DamageType::Laser), |d| *d);
+            if let Ok(splash) = world.get::<&Splash>(e) {
+                // the blast does all the damage, including to whatever was hit directly
+                explosions.push((ix, iy, *splash, dmg));
+                tile_hits.push(IntRect::new(ix - 12, iy - 12, 24, 24));
+                continue;
+            }
+            match impact {
+                Impact::Wall => {
+                    if world.satisfies::<&DamageEnemies>(e).unwrap_or(false) {
+                        // step into the surface that was hit
+                        let mut r = rect.clone();
+                        r.x -= hit.normal.x as i32;
+                        r.y -= hit.normal.y as i32;
+                        tile_hits.push(r);
+                    }
+                }
+                Impact::Enemy(id) => {
+                    let mut en = world.get::<&mut EnemyHittable>(id).unwrap();
+                    en.hurt(&mut world.get::<&mut Health>(id).unwrap(), dmg);
+                    if let Ok(k) = world.get::<&Knockback>(e) {
+                        en.knock_back(Impulse::new(rect.centre(), k.0));
+                    }
+                }
+                Impact::Player => {
+                    let force = world.get::<&Knockback>(e).map_or(0.0, |k| k.0);
+                    world
+                        .get::<&mut Controller>(resources.player_id)
+                        .unwrap()
+                        .hurt(
+                            &mut world.get::<&mut Health>(resources.player_id).unwrap(),
+                            dmg,
+                            Impulse::new(rect.centre(), force),
+                        );
+                }
+            }
+        }
+        for (x, y, splash, dmg) in explosions {
+            create_explosion(buffer, x, y);
+            apply_splash(
+                &world,
+                &resources.dynamic_index,
+                resources.player_id,
+                vec2(x as f32, y as f32),
+                splash,
+                dmg,
+            );
+        }
+        for rect in tile_hits {
+            damage_tiles(&world, &mut resources.body_index, buffer, &rect);
         }
 
         for (_, (proj, _)) in world
@@ -123,53 +283,157 @@ impl Projectile {
     }
 }
 
-fn spawn_mini_fireballs(buffer: &mut CommandBuffer, x: i32, y: i32) {
-    buffer.spawn((Explosion::new_from_centre(x, y),));
-    let mut a = quad_rand::gen_range(0.0, std::f32::consts::TAU);
-    a += std::f32::consts::TAU / std::f32::consts::E;
-    let rect = IntRect::new(x - 4, y - 4, 8, 8);
-    for _ in 0..6 {
-        make_enemy_fireball(buffer, rect.clone(), a.cos() * 2.0, a.sin() * 2.0, false);
-        a += std::f32::consts::TAU / std::f32::consts::E;
+impl Beam {
+    pub fn update(resources: &mut SceneResources, buffer: &mut CommandBuffer) {
+        let world = resources.world_ref.lock().unwrap();
+        let mut tile_hits = Vec::new();
+        for (e, beam) in world.query::<&Beam>().iter() {
+            buffer.despawn(e);
+            let dir = beam.dir as f32;
+            // a point-sized probe finds how far the beam gets before hitting a wall
+            let probe = IntRect::new(beam.x as i32, beam.y as i32, 1, 1);
+            let wall = sweep_tiles(&world, &resources.body_index, &probe, dir * beam.range, 0.0);
+            let length = wall.as_ref().map_or(beam.range, |hit| hit.t * beam.range);
+            let x1 = beam.x + dir * length;
+
+            // everything alive the beam crosses, nearest first
+            let mut targets = Vec::new();
+            let (left, right) = (beam.x.min(x1), beam.x.max(x1));
+            for id in resources
+                .dynamic_index
+                .entities_on_ray(beam.x, beam.y, x1, beam.y)
+            {
+                let mut q = match world.query_one::<(&EnemyHittable, &Health, &IntRect)>(id) {
+                    Ok(q) => q,
+                    Err(_) => continue,
+                };
+                if let Some((_, health, rect)) = q.get() {
+                    let crosses = beam.y >= rect.y as f32
+                        && beam.y < (rect.y + rect.h) as f32
+                        && left < (rect.x + rect.w) as f32
+                        && right > rect.x as f32;
+                    if health.is_alive() && crosses {
+                        let near_x = if dir > 0.0 {
+                            rect.x as f32
+                        } else {
+                            (rect.x + rect.w) as f32
+                        };
+                        targets.push((((near_x - beam.x) * dir).max(0.0), id));
+                    }
+                }
+            }
+            targets.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let max_targets = world
+                .get::<&Pierce>(e)
+                .map_or(1, |p| p.remaining as usize + 1);
+            targets.truncate(max_targets);
+
+            let dmg = world
+                .get::<&Damage>(e)
+                .map_or(Damage::new(1, DamageType::Laser), |d| *d);
+            let force = world.get::<&Knockback>(e).map_or(0.0, |k| k.0);
+            for (d, id) in &targets {
+                let x = beam.x + dir * d;
+                let mut en = world.get::<&mut EnemyHittable>(*id).unwrap();
+                en.hurt(&mut world.get::<&mut Health>(*id).unwrap(), dmg);
+                if force > 0.0 {
+                    en.knock_back(Impulse::new(vec2(x - dir, beam.y), force));
+                }
+                buffer.spawn((ZapFlash::new_from_centre(x as i32, beam.y as i32),));
+            }
+
+            // stopped by the last enemy it could get through, otherwise it reaches the wall
+            let end = if targets.len() == max_targets {
+                beam.x + dir * targets.last().unwrap().0
+            } else {
+                if let Some(hit) = &wall {
+                    let mut r = probe.clone();
+                    // step into the surface that was hit
+                    r.x = x1.round() as i32 - hit.normal.x as i32;
+                    tile_hits.push(r);
+                    buffer.spawn((ZapFlash::new_from_centre(x1 as i32, beam.y as i32),));
+                }
+                x1
+            };
+            buffer.spawn((BeamEffect::new(beam.x, beam.y, end, beam.y),));
+        }
+        for rect in tile_hits {
+            damage_tiles(&world, &mut resources.body_index, buffer, &rect);
+        }
     }
 }
 
-fn find_collision_pos(
+// how far a point is from the nearest part of a rect
+fn distance_to_rect(p: Vec2, rect: &IntRect) -> f32 {
+    let x = p.x.clamp(rect.x as f32, (rect.x + rect.w) as f32);
+    let y = p.y.clamp(rect.y as f32, (rect.y + rect.h) as f32);
+    p.distance(vec2(x, y))
+}
+
+// damage and knockback fall off linearly to nothing at the edge of the blast
+fn apply_splash(
     world: &World,
-    resources: &SceneResources,
-    ox: i32,
-    oy: i32,
-    rect: &IntRect,
-) -> (i32, i32) {
-    // this function can be slow as it's only called to generate the vfx when a projectile hits a wall
-    // but it should be better than this because there is already code to do this more efficiently elsewhere!
-    let mut r = rect.clone();
-    let dx = (ox - r.x).signum();
-    while r.x != ox {
-        r.x += dx;
-        if !collide_any(world, &resources.body_index, &r) {
-            return (r.x, r.y);
+    index: &DynamicIndex,
+    player_id: Entity,
+    centre: Vec2,
+    splash: Splash,
+    dmg: Damage,
+) {
+    let r = splash.radius;
+    let area = IntRect::new(
+        (centre.x - r) as i32,
+        (centre.y - r) as i32,
+        (r * 2.0) as i32,
+        (r * 2.0) as i32,
+    );
+    for id in index.entities(&area).iter().copied() {
+        let rect = match world.get::<&IntRect>(id) {
+            Ok(rect) => rect.clone(),
+            Err(_) => continue,
+        };
+        let falloff = 1.0 - distance_to_rect(centre, &rect) / r;
+        if falloff <= 0.0 {
+            continue;
         }
-    }
-    let dy = (oy - r.y).signum();
-    while r.y != oy {
-        r.y += dy;
-        if !collide_any(world, &resources.body_index, &r) {
-            return (r.x, r.y);
+        let amount = (dmg.amount as f32 * falloff).ceil();
+        let impulse = Impulse::blast(centre, splash.force * falloff);
+        if id == player_id {
+            let mut q = match world.query_one::<(&mut Controller, &mut Health)>(id) {
+                Ok(q) => q,
+                Err(_) => continue,
+            };
+            if let Some((c, health)) = q.get() {
+                let amount = (amount * SELF_SPLASH_SCALE) as u16;
+                if amount > 0 {
+                    c.hurt(health, Damage::new(amount, dmg.typ), impulse);
+                }
+                // always get thrown, which is what makes rocket jumping possible
+                c.blast(impulse);
+            }
+        } else {
+            let mut q = match world.query_one::<(&mut EnemyHittable, &mut Health)>(id) {
+                Ok(q) => q,
+                Err(_) => continue,
+            };
+            if let Some((en, health)) = q.get() {
+                if health.is_alive() {
+                    en.hurt(health, Damage::new(amount as u16, dmg.typ));
+                    en.knock_back(impulse);
+                }
+            }
         }
     }
-    (r.x, r.y)
 }
 
-pub fn make_player_projectile(buffer: &mut CommandBuffer, rect: IntRect, vx: f32) {
-    let proj = Projectile::new(&rect, vx, 0.0);
-    buffer.spawn((
-        rect,
-        ZapSprite::new(),
-        proj,
-        DamageEnemies {},
-        LaserImpact {},
-    ));
+fn spawn_mini_fireballs(buffer: &mut CommandBuffer, x: i32, y: i32) {
+    buffer.spawn((Explosion::new_from_centre(x, y),));
+    let mut a = quad_rand::gen_range(0.0, std::f32::consts::TAU);
+    a += std::f32::consts::TAU / std::f32::consts::E;
+    let rect = IntRect::new(x - 4, y - 4, 8, 8);
+    for _ in 0..6 {
+        make_enemy_fireball(buffer, rect.clone(), a.cos() * 2.0, a.sin() * 2.0, false);
+        a += std::f32::consts::TAU / std::f32::consts::E;
+    }
 }
 
 pub fn make_enemy_laser(buffer: &mut CommandBuffer, rect: IntRect, vx: f32) {
@@ -179,7 +443,10 @@ pub fn make_enemy_laser(buffer: &mut CommandBuffer, rect: IntRect, vx: f32) {
         ZapSprite::new(),
         proj,
         DamagePlayer {},
+        Indexed {},
         LaserImpact {},
+        Knockback(4.0),
+        Damage::new(1, DamageType::Laser),
     ));
 }
 
@@ -197,7 +464,10 @@ pub fn make_enemy_fireball(
             FireballEffect::new(8.0),
             proj,
             DamagePlayer {},
+            Indexed {},
             FireballSplit {},
+            Knockback(6.0),
+            Damage::new(1, DamageType::Explosive),
         ));
     } else {
         buffer.spawn((
@@ -205,7 +475,10 @@ pub fn make_enemy_fireball(
             FireballEffect::new(4.0),
             proj,
             DamagePlayer {},
+            Indexed {},
             ProjectileGravity {},
+            Knockback(4.0),
+            Damage::new(1, DamageType::Fire),
         ));
     }
 }
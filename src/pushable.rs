@@ -0,0 +1,41 @@
+use crate::draw::ColorRect;
+use crate::physics::{Actor, IntRect, SolidActor};
+use crate::resources::SceneResources;
+use crate::vfx::create_explosion;
+use hecs::{CommandBuffer, World};
+use macroquad::prelude::*;
+
+const CRATE_COLOR: Color = Color {
+    r: 0.6,
+    g: 0.4,
+    b: 0.2,
+    a: 1.0,
+};
+
+pub struct PushableCrate {}
+
+pub fn add_crate(world: &mut World, x: i32, y: i32) {
+    let rect = IntRect::new(x - 8, y - 16, 16, 16);
+    let draw = ColorRect::new(CRATE_COLOR);
+    let actor = Actor::new(&rect, 0.4);
+    world.spawn((
+        rect,
+        draw,
+        actor,
+        SolidActor { pushable: true },
+        PushableCrate {},
+    ));
+}
+
+impl PushableCrate {
+    pub fn update(resources: &SceneResources, buffer: &mut CommandBuffer) {
+        let world = resources.world_ref.lock().unwrap();
+        for (id, (actor, rect, _)) in world.query::<(&Actor, &IntRect, &PushableCrate)>().iter() {
+            if actor.crushed {
+                buffer.despawn(id);
+                let (x, y) = rect.centre_int();
+                create_explosion(buffer, x, y);
+            }
+        }
+    }
+}
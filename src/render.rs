@@ -1,7 +1,9 @@
-use crate::draw::{draw_sprites, draw_tiles};
+use crate::draw::{draw_index_overlay, draw_sprites, draw_tiles, ARMOR_COLOR, OVERHEAL_COLOR};
+use crate::health::Health;
 use crate::level::LevelInfo;
 use crate::messages::Messages;
-use crate::player::Controller;
+use crate::player::{Controller, PLAYER_MAX_HP};
+use crate::powerup::PowerUpType;
 use crate::profile::{Phase, Profiler};
 use crate::resources::{GlobalAssets, SceneResources};
 use crate::scene::Scene;
@@ -9,7 +11,7 @@ use crate::stats::LevelStats;
 use crate::transition::{new_transition, TransitionEffect, TransitionEffectType};
 use crate::vfx::draw_vfx;
 use crate::visibility::draw_visibility;
-use crate::weapon::{ammo_symbol, weapon_name, weapon_sprite_frame, weapon_v_offset, AmmoType};
+use crate::weapon::{ammo_symbol, AmmoType};
 use enum_iterator::all;
 use macroquad::prelude::*;
 use miniquad::graphics::{BlendFactor, BlendState, BlendValue, Equation};
@@ -52,6 +54,7 @@ pub struct Renderer {
     jfa_init_material: Material,
     jfa_step_material: Material,
     jfa_final_material: Material,
+    pub show_index: bool,
 }
 
 impl Renderer {
@@ -118,6 +121,7 @@ impl Renderer {
             jfa_init_material,
             jfa_step_material,
             jfa_final_material,
+            show_index: false,
         }
     }
 
@@ -319,10 +323,12 @@ impl Renderer {
         ));
 
         let mut world = resources.world_ref.lock().unwrap();
-        let (flash, hp) = match world.get::<&Controller>(resources.player_id) {
-            Ok(c) => (c.was_hurt(), c.hp),
-            Err(_) => (false, 0),
-        };
+        let flash = world
+            .get::<&Controller>(resources.player_id)
+            .map_or(false, |c| c.was_hurt());
+        let (hp, max_hp, armor) = world
+            .get::<&Health>(resources.player_id)
+            .map_or((0, PLAYER_MAX_HP, 0), |h| (h.hp, h.max_hp, h.armor));
         if flash {
             clear_background(RED);
             return;
@@ -349,6 +355,9 @@ impl Renderer {
         set_camera(&c); // complete rendering now so profiling is accurate
         profiler.start(Phase::DrawSprites);
         draw_sprites(&mut world, resources, assets);
+        if self.show_index {
+            draw_index_overlay(&world, resources);
+        }
         set_default_camera(); // complete rendering now so profiling is accurate
 
         profiler.start(Phase::DrawEffects);
@@ -456,19 +465,42 @@ impl Renderer {
             draw_text(m, wvdc, y, 16.0, WHITE);
             y += Messages::HEIGHT as f32;
         }
-        for ii in 0..3 {
-            let sy = if ii < hp { 0.0 } else { 16.0 };
+        // hearts, then any overheal, then armor
+        let slots = max_hp.max(hp) + armor;
+        for ii in 0..slots {
+            let (sy, color) = if ii < max_hp {
+                (if ii < hp { 0.0 } else { 16.0 }, WHITE)
+            } else if ii < hp {
+                (0.0, OVERHEAL_COLOR)
+            } else {
+                (0.0, ARMOR_COLOR)
+            };
             draw_texture_ex(
                 assets.ui_sprite,
                 wvdc + 16.0 * ii as f32,
                 self.height - wvdc - 16.0,
-                WHITE,
+                color,
                 DrawTextureParams {
                     source: Some(Rect::new(0.0, sy, 16.0, 16.0)),
                     ..Default::default()
                 },
             );
         }
+        // held keys sit just above the hearts
+        let mut key_x = wvdc;
+        for (key, held) in resources.keys.iter() {
+            if *held {
+                draw_rectangle(
+                    key_x + 4.0,
+                    self.height - wvdc - 28.0,
+                    8.0,
+                    10.0,
+                    key.color(),
+                );
+                key_x += 12.0;
+            }
+        }
+        let ammo_x = wvdc + 16.0 * slots as f32 + 2.0;
         let w = &resources.weapons[0];
         let t = w.get_ammo_type();
         let n = w.get_ammo_use();
@@ -476,7 +508,7 @@ impl Renderer {
             let color = if resources.ammo[t] >= n { WHITE } else { RED };
             draw_text(
                 &format!("{:02}", resources.ammo[t]),
-                wvdc + 50.0,
+                ammo_x,
                 self.height - wvdc - 3.0,
                 16.0,
                 color,
@@ -491,11 +523,21 @@ impl Renderer {
                 y -= 12.0;
             }
         }
+        // power-up countdowns down the top right
+        let mut y = wvdc + 9.0;
+        for typ in all::<PowerUpType>() {
+            if let Some(secs) = resources.powerups.seconds_left(typ) {
+                let t = format!("{} {:02}", typ.symbol(), secs);
+                let m = measure_text(&t, None, 16, 1.0);
+                draw_text(&t, self.width - wvdc - m.width, y, 16.0, typ.color());
+                y += 12.0;
+            }
+        }
         if !resources.selector.hidden {
             let offset = resources.selector.offset;
             let typ = resources.weapons[0].get_type();
             if resources.selector.timer > 0 {
-                self.draw_centred_text(weapon_name(typ), 16, 184.0);
+                self.draw_centred_text(&resources.weapon_defs.get(typ).name, 16, 184.0);
             }
             unsafe { get_internal_gl() }
                 .quad_gl
@@ -503,12 +545,13 @@ impl Renderer {
             for didx in (offset.floor() as i32)..=(offset.ceil() as i32) {
                 let idx = didx.rem_euclid(resources.weapons.len() as i32);
                 let typ = resources.weapons[idx as usize].get_type();
-                let frame = weapon_sprite_frame(typ);
+                let def = resources.weapon_defs.get(typ);
+                let frame = def.sprite;
                 draw_texture_ex(
                     assets.weapon_sprite,
                     self.width / 2.0 - 12.0 + didx as f32 * 50.0
                         - (resources.selector.offset * 50.0).round(),
-                    wvdc + 184.0 - weapon_v_offset(typ),
+                    wvdc + 184.0 - def.v_offset,
                     WHITE,
                     DrawTextureParams {
                         source: Some(Rect::new(0.0, 16.0 * frame as f32, 24.0, 16.0)),
@@ -517,6 +560,14 @@ impl Renderer {
                 );
             }
             unsafe { get_internal_gl() }.quad_gl.scissor(None);
+            // a pip for each upgrade fitted to the selected weapon
+            let mut x = self.width / 2.0 - 12.0;
+            for (upgrade, level) in resources.weapons[0].get_upgrades() {
+                for _ in 0..level {
+                    draw_rectangle(x, wvdc + 197.0, 2.0, 2.0, upgrade.color());
+                    x += 3.0;
+                }
+            }
         }
     }
 
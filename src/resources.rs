@@ -1,12 +1,21 @@
-use crate::index::SpatialIndex;
+use crate::checkpoint::CheckpointState;
+use crate::door::KeyColor;
+use crate::enemy_defs::{load_enemy_defs, EnemyDefs};
+use crate::health::{DeathEvent, Health};
+use crate::index::{DynamicIndex, SpatialIndex};
 use crate::level::{load_level_info, LevelInfo};
 use crate::messages::Messages;
+use crate::movement::MovementParams;
+use crate::player::PLAYER_MAX_HP;
+use crate::powerup::PowerUps;
 use crate::render::load_flash_material;
 use crate::scene::Scene;
 use crate::script::ScriptEngine;
 use crate::stats::LevelStats;
 use crate::transition::TransitionEffectType;
-use crate::weapon::{AmmoQuantity, AmmoType, Weapon, WeaponSelectorUI, WeaponType};
+use crate::weapon::{AmmoQuantity, AmmoType, Upgrades, Weapon, WeaponSelectorUI};
+use crate::weapon_defs::{load_weapon_defs, WeaponDefs, WeaponType};
+use bitflags::bitflags;
 use enum_map::EnumMap;
 use hecs::{Entity, World};
 use macroquad::prelude::*;
@@ -14,12 +23,17 @@ use std::collections::{HashSet, VecDeque};
 use std::num::NonZeroU8;
 use std::sync::{Arc, Mutex};
 
+bitflags! {
+    pub struct Abilities: u8 {
+        const WALL_JUMP = 0b00000001;
+        const DOUBLE_JUMP = 0b00000010;
+        const DASH = 0b00000100;
+    }
+}
+
 pub struct GlobalAssets {
     pub sky: Texture2D,
     pub player_sprite: Texture2D,
-    pub dog_sprite: Texture2D,
-    pub parrot_sprite: Texture2D,
-    pub parrot_sprite2: Texture2D,
     pub pickup_sprite: Texture2D,
     pub switch_sprite: Texture2D,
     pub ui_sprite: Texture2D,
@@ -28,6 +42,9 @@ pub struct GlobalAssets {
     pub interstitial: Texture2D,
     pub flash_material: Material,
     pub levels: Vec<LevelInfo>,
+    // loaded once, with each level getting its own copy
+    pub weapon_defs: WeaponDefs,
+    pub enemy_defs: EnemyDefs,
     // should this be here?
     pub next_scene: Option<(Scene, TransitionEffectType)>,
 }
@@ -37,9 +54,6 @@ pub async fn load_assets() -> GlobalAssets {
     GlobalAssets {
         sky: load_texture("sky.png").await.unwrap(),
         player_sprite: load_texture("princess.png").await.unwrap(),
-        dog_sprite: load_texture("robodog.png").await.unwrap(),
-        parrot_sprite: load_texture("spiderparrot.png").await.unwrap(),
-        parrot_sprite2: load_texture("greenparrot.png").await.unwrap(),
         pickup_sprite: load_texture("pickup.png").await.unwrap(),
         switch_sprite: load_texture("switch.png").await.unwrap(),
         ui_sprite: load_texture("ui-heart.png").await.unwrap(),
@@ -48,6 +62,8 @@ pub async fn load_assets() -> GlobalAssets {
         interstitial: load_texture("interstitial.png").await.unwrap(),
         flash_material: load_flash_material(),
         levels,
+        weapon_defs: load_weapon_defs().await.unwrap(),
+        enemy_defs: load_enemy_defs().await.unwrap(),
         next_scene: None,
     }
 }
@@ -61,20 +77,56 @@ pub struct SceneResources {
     pub death_timer: Option<NonZeroU8>,
     pub draw_order: Vec<Entity>,
     pub body_index: SpatialIndex,
+    pub dynamic_index: DynamicIndex,
     pub tileset_info: TilesetInfo,
+    pub movement: MovementParams,
     pub messages: Messages,
     pub selector: WeaponSelectorUI,
     pub stats: LevelStats,
     pub triggers: HashSet<String>,
+    // everything that died this frame
+    pub deaths: Vec<DeathEvent>,
+    pub weapon_defs: WeaponDefs,
+    pub enemy_defs: EnemyDefs,
     pub weapons: VecDeque<Box<dyn Weapon>>,
     pub ammo: EnumMap<AmmoType, AmmoQuantity>,
+    pub abilities: Abilities,
+    // abilities the level grants but which aren't carried over to the next one
+    pub level_abilities: Abilities,
+    // keys only open doors in the level they were found in
+    pub keys: EnumMap<KeyColor, bool>,
+    pub powerups: PowerUps,
+    pub checkpoint: Option<CheckpointState>,
+    // what the player was carrying on entering the level, restored if they die
+    // before reaching a checkpoint
+    pub start_inventory: Inventory,
 }
 
 impl SceneResources {
+    pub fn has_ability(&self, ability: Abilities) -> bool {
+        (self.abilities | self.level_abilities).contains(ability)
+    }
+
+    pub fn update_dynamic_index(&mut self) {
+        let world = self.world_ref.lock().unwrap();
+        self.dynamic_index.update(&world);
+    }
+
     pub fn persist_inventory(&self) -> Inventory {
+        let world = self.world_ref.lock().unwrap();
+        let (max_hp, armor) = world
+            .get::<&Health>(self.player_id)
+            .map_or((PLAYER_MAX_HP, 0), |h| (h.max_hp, h.armor));
         Inventory {
-            weapon_types: self.weapons.iter().map(|w| w.get_type()).collect(),
+            weapon_types: self
+                .weapons
+                .iter()
+                .map(|w| (w.get_type(), w.get_upgrades()))
+                .collect(),
             ammo: self.ammo,
+            abilities: self.abilities,
+            max_hp,
+            armor,
             is_default: false,
         }
     }
@@ -82,16 +134,22 @@ impl SceneResources {
 
 #[derive(Clone)]
 pub struct Inventory {
-    pub weapon_types: Vec<WeaponType>,
+    pub weapon_types: Vec<(WeaponType, Upgrades)>,
     pub ammo: EnumMap<AmmoType, AmmoQuantity>,
+    pub abilities: Abilities,
+    pub max_hp: u16,
+    pub armor: u16,
     pub is_default: bool,
 }
 
 impl Inventory {
     pub fn new() -> Self {
         Self {
-            weapon_types: vec![WeaponType::BackupLaser],
+            weapon_types: vec![(WeaponType::BACKUP_LASER, Upgrades::default())],
             ammo: EnumMap::default(),
+            abilities: Abilities::empty(),
+            max_hp: PLAYER_MAX_HP,
+            armor: 0,
             is_default: true,
         }
     }
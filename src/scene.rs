@@ -1,6 +1,6 @@
 use crate::level::LevelInfo;
 use crate::loader::load_level;
-use crate::resources::Inventory;
+use crate::resources::{GlobalAssets, Inventory};
 use crate::{resources::SceneResources, stats::LevelStats};
 use macroquad::experimental::coroutines::{start_coroutine, Coroutine};
 
@@ -12,8 +12,18 @@ pub enum Scene {
     PostLevel(LevelStats, Inventory),
 }
 
-pub async fn new_prelevel(info: LevelInfo, inv: Inventory, fast: bool) -> Scene {
-    let coro: Coroutine<Result<Scene, String>> = start_coroutine(load_level(info.clone(), inv));
+pub async fn new_prelevel(
+    info: LevelInfo,
+    inv: Inventory,
+    fast: bool,
+    assets: &GlobalAssets,
+) -> Scene {
+    let coro: Coroutine<Result<Scene, String>> = start_coroutine(load_level(
+        info.clone(),
+        inv,
+        assets.weapon_defs.clone(),
+        assets.enemy_defs.clone(),
+    ));
     if coro.is_done() {
         let res = coro.retrieve();
         assert!(res.is_some());
@@ -1,4 +1,6 @@
-use crate::physics::{Actor, IntRect};
+use crate::index::Indexed;
+use crate::physics::{is_heavy, Actor, IntRect};
+use crate::resources::SceneResources;
 use hecs::{Entity, World};
 
 pub fn add_switch(world: &mut World, name: String, x: i32, y: i32) -> Entity {
@@ -13,6 +15,7 @@ pub fn add_switch(world: &mut World, name: String, x: i32, y: i32) -> Entity {
             name,
             enabled: true,
         },
+        Indexed {},
     ))
 }
 
@@ -20,3 +23,38 @@ pub struct Switch {
     pub name: String,
     pub enabled: bool,
 }
+
+pub fn add_pressure_plate(world: &mut World, name: String, x: i32, y: i32) -> Entity {
+    let rect = IntRect::new(x - 8, y - 4, 16, 4);
+    world.spawn((
+        rect,
+        PressurePlate {
+            name,
+            pressed: false,
+        },
+    ))
+}
+
+pub struct PressurePlate {
+    pub name: String,
+    pub pressed: bool,
+}
+
+impl PressurePlate {
+    pub fn update(resources: &mut SceneResources) {
+        let world = resources.world_ref.lock().unwrap();
+        for (_, (rect, plate)) in world.query::<(&IntRect, &mut PressurePlate)>().iter() {
+            let pressed = world
+                .query::<(&Actor, &IntRect)>()
+                .iter()
+                .any(|(id, (_, a_rect))| a_rect.intersects(rect) && is_heavy(&world, id));
+            if pressed != plate.pressed {
+                plate.pressed = pressed;
+                let event = if pressed { "press" } else { "release" };
+                resources
+                    .triggers
+                    .insert(format!("{}_{}", plate.name, event).to_owned());
+            }
+        }
+    }
+}
@@ -42,6 +42,29 @@ impl ZapFlash {
     }
 }
 
+// the trail left by a hitscan weapon, which fades quickly
+pub struct BeamEffect {
+    pub x0: f32,
+    pub y0: f32,
+    pub x1: f32,
+    pub y1: f32,
+    pub n: u32,
+}
+
+impl BeamEffect {
+    pub fn new(x0: f32, y0: f32, x1: f32, y1: f32) -> Self {
+        Self {
+            x0,
+            y0,
+            x1,
+            y1,
+            n: 0,
+        }
+    }
+}
+
+const BEAM_FRAMES: u32 = 8;
+
 pub struct Explosion {
     pub x: i32,
     pub y: i32,
@@ -130,6 +153,12 @@ pub fn update_vfx(resources: &SceneResources, buffer: &mut CommandBuffer) {
             buffer.despawn(id);
         }
     }
+    for (id, b) in world.query::<&mut BeamEffect>().iter() {
+        b.n += 1;
+        if b.n > BEAM_FRAMES {
+            buffer.despawn(id);
+        }
+    }
     for (id, z) in world.query::<&mut Explosion>().iter() {
         z.n += 1;
         if z.n > 6 {
@@ -183,6 +212,13 @@ pub fn draw_vfx(world: &World) {
     for (_, fp) in world.query::<&FireParticle>().iter() {
         draw_circle(fp.x, fp.y, fp.r * 0.75, EXPLOSION_INNER_COLOR);
     }
+    for (_, b) in world.query::<&BeamEffect>().iter() {
+        let a = 1.0 - b.n as f32 / (BEAM_FRAMES + 1) as f32;
+        let outer = Color { a, ..SKYBLUE };
+        let inner = Color { a, ..WHITE };
+        draw_line(b.x0, b.y0, b.x1, b.y1, 3.0 * a + 1.0, outer);
+        draw_line(b.x0, b.y0, b.x1, b.y1, 1.0, inner);
+    }
 
     for (_, ex) in world.query::<&Explosion>().iter() {
         match ex.n.cmp(&0) {
@@ -47,84 +47,96 @@ impl Obscurers {
 pub fn compute_obscurers(world: &mut World) {
     let mut new: Vec<(Entity, Obscurers)> = Vec::new();
     for (id, body) in world.query::<&TileBody>().iter() {
-        let mut o = Obscurers::new();
-        let cw = body.width;
-        let ch = body.data.len() as i32 / body.width;
-        for cx in 0..=body.width {
-            let mut sly: Option<i32> = None;
-            let mut sry: Option<i32> = None;
-            for cy in 0..=ch {
-                let index = (cy * body.width + cx) as usize;
-                let is_left_edge = cy < ch
-                    && cx < cw
-                    && body.data[index].is_obscurer()
-                    && (cx == 0 || !body.data[index - 1].is_obscurer());
-                let is_right_edge = cy < ch
-                    && cx > 0
-                    && body.data[index - 1].is_obscurer()
-                    && (cx == cw || !body.data[index].is_obscurer());
-                if is_left_edge && sly.is_none() {
-                    sly = Some(cy);
-                } else if !is_left_edge && sly.is_some() {
-                    o.lefts.push(Left {
-                        x: (cx * body.size) as f32,
-                        y: (sly.unwrap() * body.size) as f32,
-                        h: ((cy - sly.unwrap()) * body.size) as f32,
-                    });
-                    sly = None;
-                }
-                if is_right_edge && sry.is_none() {
-                    sry = Some(cy);
-                } else if !is_right_edge && sry.is_some() {
-                    o.rights.push(Right {
-                        x: (cx * body.size) as f32,
-                        y: (sry.unwrap() * body.size) as f32,
-                        h: ((cy - sry.unwrap()) * body.size) as f32,
-                    });
-                    sry = None;
-                }
-            }
-        }
+        new.push((id, body_obscurers(body)));
+    }
+    for (id, obs) in new.into_iter() {
+        world.insert_one(id, obs).unwrap();
+    }
+}
+
+// recompute the obscurers for a single body after its tiles have changed
+pub fn update_obscurers(world: &World, id: Entity) {
+    let body = world.get::<&TileBody>(id).unwrap();
+    if let Ok(mut obs) = world.get::<&mut Obscurers>(id) {
+        *obs = body_obscurers(&body);
+    }
+}
+
+fn body_obscurers(body: &TileBody) -> Obscurers {
+    let mut o = Obscurers::new();
+    let cw = body.width;
+    let ch = body.data.len() as i32 / body.width;
+    for cx in 0..=body.width {
+        let mut sly: Option<i32> = None;
+        let mut sry: Option<i32> = None;
         for cy in 0..=ch {
-            let mut stx: Option<i32> = None;
-            let mut sbx: Option<i32> = None;
-            for cx in 0..=cw {
-                let index = (cy * body.width + cx) as usize;
-                let is_top_edge = cx < cw
-                    && cy < ch
-                    && body.data[index].is_obscurer()
-                    && (cy == 0 || !body.data[index - body.width as usize].is_obscurer());
-                let is_bottom_edge = cx < cw
-                    && cy > 0
-                    && body.data[index - body.width as usize].is_obscurer()
-                    && (cy == ch || !body.data[index].is_obscurer());
-                if is_top_edge && stx.is_none() {
-                    stx = Some(cx);
-                } else if !is_top_edge && stx.is_some() {
-                    o.tops.push(Top {
-                        x: (stx.unwrap() * body.size) as f32,
-                        y: (cy * body.size) as f32,
-                        w: ((cx - stx.unwrap()) * body.size) as f32,
-                    });
-                    stx = None;
-                }
-                if is_bottom_edge && sbx.is_none() {
-                    sbx = Some(cx);
-                } else if !is_bottom_edge && sbx.is_some() {
-                    o.bottoms.push(Bottom {
-                        x: (sbx.unwrap() * body.size) as f32,
-                        y: (cy * body.size) as f32,
-                        w: ((cx - sbx.unwrap()) * body.size) as f32,
-                    });
-                    sbx = None;
-                }
+            let index = (cy * body.width + cx) as usize;
+            let is_left_edge = cy < ch
+                && cx < cw
+                && body.data[index].is_obscurer()
+                && (cx == 0 || !body.data[index - 1].is_obscurer());
+            let is_right_edge = cy < ch
+                && cx > 0
+                && body.data[index - 1].is_obscurer()
+                && (cx == cw || !body.data[index].is_obscurer());
+            if is_left_edge && sly.is_none() {
+                sly = Some(cy);
+            } else if !is_left_edge && sly.is_some() {
+                o.lefts.push(Left {
+                    x: (cx * body.size) as f32,
+                    y: (sly.unwrap() * body.size) as f32,
+                    h: ((cy - sly.unwrap()) * body.size) as f32,
+                });
+                sly = None;
+            }
+            if is_right_edge && sry.is_none() {
+                sry = Some(cy);
+            } else if !is_right_edge && sry.is_some() {
+                o.rights.push(Right {
+                    x: (cx * body.size) as f32,
+                    y: (sry.unwrap() * body.size) as f32,
+                    h: ((cy - sry.unwrap()) * body.size) as f32,
+                });
+                sry = None;
             }
         }
-        new.push((id, o));
     }
-    for (id, obs) in new.into_iter() {
-        world.insert_one(id, obs).unwrap();
+    for cy in 0..=ch {
+        let mut stx: Option<i32> = None;
+        let mut sbx: Option<i32> = None;
+        for cx in 0..=cw {
+            let index = (cy * body.width + cx) as usize;
+            let is_top_edge = cx < cw
+                && cy < ch
+                && body.data[index].is_obscurer()
+                && (cy == 0 || !body.data[index - body.width as usize].is_obscurer());
+            let is_bottom_edge = cx < cw
+                && cy > 0
+                && body.data[index - body.width as usize].is_obscurer()
+                && (cy == ch || !body.data[index].is_obscurer());
+            if is_top_edge && stx.is_none() {
+                stx = Some(cx);
+            } else if !is_top_edge && stx.is_some() {
+                o.tops.push(Top {
+                    x: (stx.unwrap() * body.size) as f32,
+                    y: (cy * body.size) as f32,
+                    w: ((cx - stx.unwrap()) * body.size) as f32,
+                });
+                stx = None;
+            }
+            if is_bottom_edge && sbx.is_none() {
+                sbx = Some(cx);
+            } else if !is_bottom_edge && sbx.is_some() {
+                o.bottoms.push(Bottom {
+                    x: (sbx.unwrap() * body.size) as f32,
+                    y: (cy * body.size) as f32,
+                    w: ((cx - sbx.unwrap()) * body.size) as f32,
+                });
+                sbx = None;
+            }
+        }
     }
+    o
 }
 
 fn extend(v: Vec2, radius: f32) -> Vec2 {
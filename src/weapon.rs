@@ -1,72 +1,18 @@
+use crate::draw::ZapSprite;
+use crate::health::{Damage, DamageType};
 use crate::input::KeyState;
 use crate::physics::{Actor, IntRect};
-use crate::projectile::{make_player_projectile, DamageEnemies, Projectile, ProjectileDrag};
+use crate::projectile::{
+    Beam, Bounce, DamageEnemies, Knockback, LaserImpact, Pierce, Projectile, ProjectileDrag,
+    ProjectileGravity, Splash,
+};
 use crate::vfx::{FireballEffect, SmokeParticle};
+use crate::weapon_defs::{AltFire, FireMode, ProjectileKind, WeaponDef, WeaponDefs, WeaponType};
 use enum_map::EnumMap;
-use hecs::CommandBuffer;
+use hecs::{CommandBuffer, EntityBuilder};
+use macroquad::color::{Color, GREEN, ORANGE, SKYBLUE};
 use std::collections::VecDeque;
 
-// eventually there will be variants whose names don't end in "...Laser"
-#[allow(clippy::enum_variant_names)]
-#[derive(Copy, Clone, PartialEq, Eq, Hash)]
-pub enum WeaponType {
-    BackupLaser,
-    BurstLaser,
-    AutoLaser,
-    DoubleLaser,
-    Shotgun,
-    SuperShotgun,
-    ReverseShotgun,
-}
-
-pub fn weapon_name(typ: WeaponType) -> &'static str {
-    match typ {
-        WeaponType::BackupLaser => "backup laser",
-        WeaponType::AutoLaser => "auto-laser",
-        WeaponType::BurstLaser => "burst laser",
-        WeaponType::DoubleLaser => "double laser",
-        WeaponType::Shotgun => "shotgun",
-        WeaponType::SuperShotgun => "super shotgun",
-        WeaponType::ReverseShotgun => "reverse shotgun",
-    }
-}
-
-pub fn weapon_name_indef(typ: WeaponType) -> &'static str {
-    match typ {
-        WeaponType::BackupLaser => unreachable!(),
-        WeaponType::AutoLaser => "an auto-laser",
-        WeaponType::BurstLaser => "a burst laser",
-        WeaponType::DoubleLaser => "a double laser",
-        WeaponType::Shotgun => "a shotgun",
-        WeaponType::SuperShotgun => "a super shotgun",
-        WeaponType::ReverseShotgun => "the reverse shotgun",
-    }
-}
-
-pub fn weapon_sprite_frame(typ: WeaponType) -> usize {
-    match typ {
-        WeaponType::BackupLaser => 0,
-        WeaponType::AutoLaser => 2,
-        WeaponType::BurstLaser => 1,
-        WeaponType::DoubleLaser => 7,
-        WeaponType::Shotgun => 3,
-        WeaponType::SuperShotgun => 4,
-        WeaponType::ReverseShotgun => 5,
-    }
-}
-
-pub fn weapon_v_offset(typ: WeaponType) -> f32 {
-    match typ {
-        WeaponType::BackupLaser => 4.0,
-        WeaponType::AutoLaser => 3.0,
-        WeaponType::BurstLaser => 3.0,
-        WeaponType::DoubleLaser => 2.0,
-        WeaponType::Shotgun => 4.0,
-        WeaponType::SuperShotgun => 3.0,
-        WeaponType::ReverseShotgun => 1.0,
-    }
-}
-
 #[derive(enum_iterator::Sequence, enum_map::Enum, Copy, Clone)]
 pub enum AmmoType {
     Cell,
@@ -107,280 +53,362 @@ pub fn ammo_max(typ: AmmoType) -> AmmoQuantity {
     }
 }
 
-pub trait Weapon {
-    fn get_type(&self) -> WeaponType;
-    fn get_ammo_type(&self) -> AmmoType;
-    fn get_ammo_use(&self) -> AmmoQuantity;
-    fn update(
-        &mut self,
-        buffer: &mut CommandBuffer,
-        player: &mut Actor,
-        player_rect: &IntRect,
-        facing: i8,
-        key_state: KeyState,
-    ) -> bool;
+// picked up separately and fitted to whichever weapon is selected at the time
+#[derive(enum_iterator::Sequence, enum_map::Enum, Copy, Clone, PartialEq, Eq)]
+pub enum UpgradeType {
+    FireRate,
+    ExtraShot,
+    AmmoSaver,
 }
 
-struct BackupLaser {}
-
-impl BackupLaser {
-    fn new() -> Self {
-        Self {}
+impl UpgradeType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fire_rate" => Some(Self::FireRate),
+            "extra_shot" => Some(Self::ExtraShot),
+            "ammo_saver" => Some(Self::AmmoSaver),
+            _ => None,
+        }
     }
-}
 
-impl Weapon for BackupLaser {
-    fn get_type(&self) -> WeaponType {
-        WeaponType::BackupLaser
-    }
-    fn get_ammo_type(&self) -> AmmoType {
-        AmmoType::Cell
-    }
-    fn get_ammo_use(&self) -> AmmoQuantity {
-        0
-    }
-    fn update(
-        &mut self,
-        buffer: &mut CommandBuffer,
-        player: &mut Actor,
-        player_rect: &IntRect,
-        facing: i8,
-        key_state: KeyState,
-    ) -> bool {
-        if key_state == KeyState::Pressed {
-            let new_x = player_rect.x + 3 + facing as i32 * 9;
-            let rect = IntRect::new(new_x, player_rect.y + 11, 8, 5);
-            make_player_projectile(buffer, rect, facing as f32 * 10.0);
-            player.vx -= facing as f32 * 10.0;
-            return true;
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::FireRate => "rapid fire",
+            Self::ExtraShot => "extra shot",
+            Self::AmmoSaver => "ammo saver",
         }
-        false
     }
-}
 
-fn make_shotgun_spray(
-    buffer: &mut CommandBuffer,
-    x: i32,
-    y: i32,
-    facing: i8,
-    n: usize,
-    spread: f32,
-) {
-    let rect = IntRect::new(x + facing as i32 * 9, y, 5, 5);
-    let vx = facing as f32 * 15.0;
-    for i in 0..n {
-        let c = rect.clone();
-        let proj = Projectile::new(
-            &c,
-            vx * quad_rand::gen_range(0.1, 1.0),
-            ((i as f32 / (n - 1) as f32) - 0.5) * spread * quad_rand::gen_range(0.8, 1.2),
-        );
-        buffer.spawn((
-            c,
-            FireballEffect::new(3.0),
-            proj,
-            DamageEnemies {},
-            ProjectileDrag {},
-        ));
-    }
-    for _ in 0..(n / 2) {
-        buffer.spawn((SmokeParticle::new_from_centre(
-            x + 2,
-            y + 2,
-            std::f32::consts::PI / -2.0 + quad_rand::gen_range(-0.3, 0.3),
-            4.0,
-        ),));
+    pub fn color(&self) -> Color {
+        match self {
+            Self::FireRate => ORANGE,
+            Self::ExtraShot => SKYBLUE,
+            Self::AmmoSaver => GREEN,
+        }
     }
 }
 
-struct Shotgun {}
+// how many of each upgrade a weapon has been given
+pub type Upgrades = EnumMap<UpgradeType, u8>;
 
-impl Shotgun {
-    fn new() -> Self {
-        Self {}
-    }
-}
+pub const MAX_UPGRADE_LEVEL: u8 = 2;
+// single shot weapons become automatic with rapid fire, at this rate
+const RAPID_FIRE_COOLDOWN: u8 = 8;
+// and burst weapons fire longer bursts
+const RAPID_FIRE_BURST: u8 = 2;
+// per level of ammo saver
+const AMMO_SAVE_CHANCE: f32 = 0.25;
+// how far apart extra shots are for weapons that only had one
+const EXTRA_SHOT_SPREAD: f32 = 6.0;
 
-impl Weapon for Shotgun {
-    fn get_type(&self) -> WeaponType {
-        WeaponType::Shotgun
-    }
-    fn get_ammo_type(&self) -> AmmoType {
-        AmmoType::Shell
-    }
-    fn get_ammo_use(&self) -> AmmoQuantity {
-        1
-    }
-    fn update(
-        &mut self,
-        buffer: &mut CommandBuffer,
-        player: &mut Actor,
-        player_rect: &IntRect,
-        facing: i8,
-        key_state: KeyState,
-    ) -> bool {
-        if key_state == KeyState::Pressed {
-            make_shotgun_spray(
-                buffer,
-                player_rect.x + 3,
-                player_rect.y + 11,
-                facing,
-                7,
-                5.0,
-            );
-            player.vx -= facing as f32 * 10.0;
-            return true;
-        }
-        false
-    }
+// the state of both fire buttons. the fire button is passed in as not pressed if
+// there isn't enough ammo for it, but alt fire says so separately, as letting go
+// of a held charge fires it
+#[derive(Copy, Clone)]
+pub struct Triggers {
+    pub fire: KeyState,
+    pub alt_fire: KeyState,
+    pub can_alt_fire: bool,
 }
-struct SuperShotgun {}
 
-impl SuperShotgun {
-    fn new() -> Self {
-        Self {}
-    }
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Shot {
+    Primary,
+    Alt,
 }
 
-impl Weapon for SuperShotgun {
-    fn get_type(&self) -> WeaponType {
-        WeaponType::SuperShotgun
-    }
-    fn get_ammo_type(&self) -> AmmoType {
-        AmmoType::Shell
-    }
-    fn get_ammo_use(&self) -> AmmoQuantity {
-        2
-    }
+pub trait Weapon {
+    fn get_type(&self) -> WeaponType;
+    fn get_ammo_type(&self) -> AmmoType;
+    fn get_ammo_use(&self) -> AmmoQuantity;
+    fn get_alt_ammo_use(&self) -> AmmoQuantity;
+    fn get_upgrades(&self) -> Upgrades;
+    // returns false if this kind of upgrade is already maxed out
+    fn upgrade(&mut self, upgrade: UpgradeType) -> bool;
+    // rolled for each shot, true means it doesn't cost any ammo
+    fn saves_ammo(&self) -> bool;
+    // damage multiplies what each projectile does, so power-ups can boost it
+    // returns which kind of shot was fired, if any, so the right ammo can be taken
     fn update(
         &mut self,
         buffer: &mut CommandBuffer,
         player: &mut Actor,
         player_rect: &IntRect,
         facing: i8,
-        key_state: KeyState,
-    ) -> bool {
-        if key_state == KeyState::Pressed {
-            make_shotgun_spray(
-                buffer,
-                player_rect.x + 3,
-                player_rect.y + 11,
-                facing,
-                15,
-                10.0,
-            );
-            player.vx -= facing as f32 * 20.0;
-            return true;
+        triggers: Triggers,
+        damage: u16,
+    ) -> Option<Shot>;
+}
+
+// frames of holding alt fire for a full charge
+const MAX_CHARGE: u8 = 30;
+// for grenades from weapons that don't explode otherwise
+const GRENADE_SPLASH: Splash = Splash {
+    radius: 32.0,
+    force: 8.0,
+};
+// grenades skip off walls and floors a couple of times before going off
+const GRENADE_BOUNCES: u8 = 2;
+const GRENADE_RESTITUTION: f32 = 0.5;
+
+// the height projectiles come out at, which drops with the player's hitbox when crouching
+fn muzzle_y(player_rect: &IntRect) -> i32 {
+    player_rect.y + player_rect.h - 13
+}
+
+// fires whatever its definition in the data file says
+struct DataWeapon {
+    typ: WeaponType,
+    // with any upgrades already applied
+    def: WeaponDef,
+    upgrades: Upgrades,
+    delay: u8,
+    shots: u8,
+    charge: u8,
+}
+
+impl DataWeapon {
+    fn apply_upgrade(&mut self, upgrade: UpgradeType) {
+        let def = &mut self.def;
+        match upgrade {
+            UpgradeType::FireRate => match def.mode {
+                FireMode::Semi => {
+                    def.mode = FireMode::Auto;
+                    def.cooldown = def.cooldown.max(RAPID_FIRE_COOLDOWN);
+                }
+                // always at least a frame faster, or small cooldowns wouldn't change
+                FireMode::Auto => {
+                    def.cooldown = def.cooldown.saturating_sub((def.cooldown / 3).max(1))
+                }
+                FireMode::Burst => def.burst += RAPID_FIRE_BURST,
+            },
+            UpgradeType::ExtraShot => {
+                def.count += 1;
+                if def.spread == 0.0 {
+                    def.spread = EXTRA_SHOT_SPREAD;
+                }
+            }
+            // checked when firing
+            UpgradeType::AmmoSaver => (),
+        }
+        self.upgrades[upgrade] += 1;
+    }
+
+    fn fire(&self, buffer: &mut CommandBuffer, player_rect: &IntRect, facing: i8, damage: u16) {
+        let def = &self.def;
+        let dir = if def.reverse { -facing } else { facing };
+        let x = player_rect.x + 3 + facing as i32 * def.muzzle;
+        let y = muzzle_y(player_rect);
+        let n = def.count;
+        for i in 0..n {
+            // fan out evenly either side of straight ahead
+            let s = if n > 1 {
+                i as f32 / (n - 1) as f32 - 0.5
+            } else {
+                0.0
+            };
+            let mut b = EntityBuilder::new();
+            let dmg = def.damage * damage;
+            match def.projectile {
+                ProjectileKind::Laser => {
+                    let rect = IntRect::new(x, y + (s * def.spread).round() as i32, 8, 5);
+                    let proj = Projectile::new(&rect, dir as f32 * def.speed, 0.0);
+                    b.add_bundle((
+                        rect,
+                        ZapSprite::new(),
+                        proj,
+                        LaserImpact {},
+                        Damage::new(dmg, DamageType::Laser),
+                    ));
+                }
+                ProjectileKind::Pellet => {
+                    let rect = IntRect::new(x, y, 5, 5);
+                    let proj = Projectile::new(
+                        &rect,
+                        dir as f32 * def.speed * quad_rand::gen_range(0.1, 1.0),
+                        s * def.spread * quad_rand::gen_range(0.8, 1.2),
+                    );
+                    b.add_bundle((
+                        rect,
+                        FireballEffect::new(3.0),
+                        proj,
+                        Damage::new(dmg, DamageType::Fire),
+                    ));
+                }
+                ProjectileKind::Rocket => {
+                    let rect = IntRect::new(x, y + (s * def.spread).round() as i32, 8, 5);
+                    let proj = Projectile::new(&rect, dir as f32 * def.speed, 0.0);
+                    b.add_bundle((
+                        rect,
+                        FireballEffect::new(3.0),
+                        proj,
+                        Damage::new(dmg, DamageType::Explosive),
+                    ));
+                }
+                ProjectileKind::Beam => {
+                    b.add_bundle((
+                        Beam {
+                            x: (x + 4) as f32,
+                            y: (y + 2) as f32 + (s * def.spread).round(),
+                            dir,
+                            range: def.range,
+                        },
+                        Damage::new(dmg, DamageType::Laser),
+                    ));
+                }
+            }
+            b.add(DamageEnemies {});
+            if def.drag {
+                b.add(ProjectileDrag {});
+            }
+            if def.knockback > 0.0 {
+                b.add(Knockback(def.knockback));
+            }
+            if def.splash_radius > 0.0 {
+                b.add(Splash {
+                    radius: def.splash_radius,
+                    force: def.splash_force,
+                });
+            }
+            if def.pierce > 0 {
+                b.add(Pierce::new(def.pierce));
+            }
+            if def.bounce > 0 {
+                b.add(Bounce::new(def.bounce, def.restitution));
+            }
+            buffer.spawn(b.build());
+        }
+        if def.projectile == ProjectileKind::Pellet {
+            for _ in 0..(n / 2) {
+                buffer.spawn((SmokeParticle::new_from_centre(
+                    x + 2,
+                    y + 2,
+                    std::f32::consts::PI / -2.0 + quad_rand::gen_range(-0.3, 0.3),
+                    4.0,
+                ),));
+            }
         }
-        false
-    }
-}
-
-struct ReverseShotgun {}
-
-impl ReverseShotgun {
-    fn new() -> Self {
-        Self {}
     }
-}
 
-impl Weapon for ReverseShotgun {
-    fn get_type(&self) -> WeaponType {
-        WeaponType::ReverseShotgun
-    }
-    fn get_ammo_type(&self) -> AmmoType {
-        AmmoType::Shell
-    }
-    fn get_ammo_use(&self) -> AmmoQuantity {
-        1
-    }
-    fn update(
+    // returns true if an alt shot went off
+    fn update_alt(
         &mut self,
         buffer: &mut CommandBuffer,
-        player: &mut Actor,
         player_rect: &IntRect,
         facing: i8,
         key_state: KeyState,
+        damage: u16,
     ) -> bool {
-        if key_state == KeyState::Pressed {
-            make_shotgun_spray(
-                buffer,
-                player_rect.x + 3 + facing as i32 * 20,
-                player_rect.y + 11,
-                -facing,
-                7,
-                5.0,
-            );
-            player.vx += facing as f32 * 10.0;
-            return true;
+        let def = &self.def;
+        match def.alt {
+            AltFire::None => false,
+            AltFire::Charge => {
+                if key_state != KeyState::NotPressed {
+                    self.charge = (self.charge + 1).min(MAX_CHARGE);
+                    return false;
+                }
+                if self.charge == 0 {
+                    return false;
+                }
+                // a tap is a normal shot, a full charge does four times the damage
+                let power = 1.0 + 3.0 * self.charge as f32 / MAX_CHARGE as f32;
+                self.charge = 0;
+                let (w, h) = ((8.0 * power) as i32, (5.0 * power.sqrt()) as i32);
+                let x = player_rect.x + 3 + facing as i32 * def.muzzle;
+                let rect = IntRect::new(x, muzzle_y(player_rect) + (5 - h) / 2, w, h);
+                let proj = Projectile::new(&rect, facing as f32 * def.speed, 0.0);
+                buffer.spawn((
+                    rect,
+                    ZapSprite::new(),
+                    proj,
+                    DamageEnemies {},
+                    LaserImpact {},
+                    Knockback(2.0 * power),
+                    Damage::new(
+                        (def.damage as f32 * power).round() as u16 * damage,
+                        DamageType::Laser,
+                    ),
+                ));
+                true
+            }
+            AltFire::Slug => {
+                if key_state != KeyState::Pressed || self.delay > 0 {
+                    return false;
+                }
+                let x = player_rect.x + 3 + facing as i32 * def.muzzle;
+                let rect = IntRect::new(x, muzzle_y(player_rect), 4, 4);
+                let proj = Projectile::new(&rect, facing as f32 * def.speed * 1.5, 0.0);
+                buffer.spawn((
+                    rect,
+                    FireballEffect::new(2.0),
+                    proj,
+                    DamageEnemies {},
+                    Knockback(def.knockback * 3.0),
+                    Damage::new(def.damage * 4 * damage, DamageType::Impact),
+                ));
+                true
+            }
+            AltFire::Grenade => {
+                if key_state != KeyState::Pressed || self.delay > 0 {
+                    return false;
+                }
+                let x = player_rect.x + 3 + facing as i32 * def.muzzle;
+                let rect = IntRect::new(x, muzzle_y(player_rect) - 2, 6, 6);
+                let proj = Projectile::new(&rect, facing as f32 * def.speed * 0.8, -4.0);
+                buffer.spawn((
+                    rect,
+                    FireballEffect::new(4.0),
+                    proj,
+                    DamageEnemies {},
+                    ProjectileGravity {},
+                    Bounce::new(GRENADE_BOUNCES, GRENADE_RESTITUTION),
+                    if def.splash_radius > 0.0 {
+                        Splash {
+                            radius: def.splash_radius,
+                            force: def.splash_force,
+                        }
+                    } else {
+                        GRENADE_SPLASH
+                    },
+                    Damage::new(def.damage * damage, DamageType::Explosive),
+                ));
+                true
+            }
         }
-        false
     }
 }
 
-struct AutoLaser {
-    delay: u8,
-}
-
-impl AutoLaser {
-    fn new() -> Self {
-        Self { delay: 0 }
-    }
-}
-
-impl Weapon for AutoLaser {
+impl Weapon for DataWeapon {
     fn get_type(&self) -> WeaponType {
-        WeaponType::AutoLaser
+        self.typ
     }
     fn get_ammo_type(&self) -> AmmoType {
-        AmmoType::Cell
+        self.def.ammo
     }
     fn get_ammo_use(&self) -> AmmoQuantity {
-        1
+        self.def.ammo_use
     }
-    fn update(
-        &mut self,
-        buffer: &mut CommandBuffer,
-        player: &mut Actor,
-        player_rect: &IntRect,
-        facing: i8,
-        key_state: KeyState,
-    ) -> bool {
-        if self.delay > 0 {
-            self.delay -= 1
-        }
-        if key_state != KeyState::NotPressed && self.delay == 0 {
-            let new_x = player_rect.x + 3 + facing as i32 * 9;
-            let rect = IntRect::new(new_x, player_rect.y + 11, 8, 5);
-            make_player_projectile(buffer, rect, facing as f32 * 10.0);
-            player.vx -= facing as f32 * 10.0;
-            self.delay = 3;
-            return true;
-        }
-        false
+    fn get_alt_ammo_use(&self) -> AmmoQuantity {
+        self.def.alt_ammo_use
     }
-}
-
-struct BurstLaser {
-    delay: u8,
-    shots: u8,
-}
-
-impl BurstLaser {
-    fn new() -> Self {
-        Self { delay: 0, shots: 0 }
+    fn get_upgrades(&self) -> Upgrades {
+        self.upgrades
     }
-}
-
-impl Weapon for BurstLaser {
-    fn get_type(&self) -> WeaponType {
-        WeaponType::BurstLaser
-    }
-    fn get_ammo_type(&self) -> AmmoType {
-        AmmoType::Cell
+    fn upgrade(&mut self, upgrade: UpgradeType) -> bool {
+        if self.upgrades[upgrade] >= MAX_UPGRADE_LEVEL {
+            return false;
+        }
+        // nothing to save on weapons that don't use ammo
+        if upgrade == UpgradeType::AmmoSaver && self.def.ammo_use == 0 && self.def.alt_ammo_use == 0
+        {
+            return false;
+        }
+        self.apply_upgrade(upgrade);
+        true
     }
-    fn get_ammo_use(&self) -> AmmoQuantity {
-        1
+    fn saves_ammo(&self) -> bool {
+        let chance = AMMO_SAVE_CHANCE * self.upgrades[UpgradeType::AmmoSaver] as f32;
+        quad_rand::gen_range(0.0, 1.0) < chance
     }
     fn update(
         &mut self,
@@ -388,76 +416,61 @@ impl Weapon for BurstLaser {
         player: &mut Actor,
         player_rect: &IntRect,
         facing: i8,
-        key_state: KeyState,
-    ) -> bool {
+        triggers: Triggers,
+        damage: u16,
+    ) -> Option<Shot> {
         if self.delay > 0 {
             self.delay -= 1
         }
-        if key_state != KeyState::NotPressed && self.delay == 0 && self.shots < 3 {
-            let new_x = player_rect.x + 3 + facing as i32 * 9;
-            let rect = IntRect::new(new_x, player_rect.y + 11, 8, 5);
-            make_player_projectile(buffer, rect, facing as f32 * 10.0);
-            player.vx -= facing as f32 * 10.0;
-            self.delay = 2;
-            self.shots += 1;
-            return true;
+        if !triggers.can_alt_fire {
+            // running out part way through a charge loses it rather than firing it
+            self.charge = 0;
+        } else if self.update_alt(buffer, player_rect, facing, triggers.alt_fire, damage) {
+            player.vx -= facing as f32 * self.def.recoil;
+            self.delay = self.def.cooldown;
+            return Some(Shot::Alt);
         }
+        let key_state = triggers.fire;
+        let triggered = match self.def.mode {
+            FireMode::Semi => key_state == KeyState::Pressed,
+            FireMode::Auto => key_state != KeyState::NotPressed,
+            FireMode::Burst => key_state != KeyState::NotPressed && self.shots < self.def.burst,
+        };
         if key_state == KeyState::NotPressed {
             self.shots = 0;
         }
-        false
+        if triggered && self.delay == 0 {
+            self.fire(buffer, player_rect, facing, damage);
+            let dir = if self.def.reverse { -facing } else { facing };
+            player.vx -= dir as f32 * self.def.recoil;
+            self.delay = self.def.cooldown;
+            self.shots = self.shots.saturating_add(1);
+            return Some(Shot::Primary);
+        }
+        None
     }
 }
 
-struct DoubleLaser {}
-
-impl DoubleLaser {
-    fn new() -> Self {
-        Self {}
-    }
+pub fn new_weapon(defs: &WeaponDefs, typ: WeaponType) -> Box<dyn Weapon> {
+    upgraded_weapon(defs, typ, Upgrades::default())
 }
 
-impl Weapon for DoubleLaser {
-    fn get_type(&self) -> WeaponType {
-        WeaponType::DoubleLaser
-    }
-    fn get_ammo_type(&self) -> AmmoType {
-        AmmoType::Cell
-    }
-    fn get_ammo_use(&self) -> AmmoQuantity {
-        2
-    }
-    fn update(
-        &mut self,
-        buffer: &mut CommandBuffer,
-        player: &mut Actor,
-        player_rect: &IntRect,
-        facing: i8,
-        key_state: KeyState,
-    ) -> bool {
-        if key_state == KeyState::Pressed {
-            let new_x = player_rect.x + 3 + facing as i32 * 9;
-            let rect = IntRect::new(new_x, player_rect.y + 8, 8, 5);
-            make_player_projectile(buffer, rect, facing as f32 * 10.0);
-            let rect = IntRect::new(new_x, player_rect.y + 14, 8, 5);
-            make_player_projectile(buffer, rect, facing as f32 * 10.0);
-            player.vx -= facing as f32 * 10.0;
-            return true;
+// for weapons carried over from a previous level or dropped and picked up again
+pub fn upgraded_weapon(defs: &WeaponDefs, typ: WeaponType, upgrades: Upgrades) -> Box<dyn Weapon> {
+    let mut w = DataWeapon {
+        typ,
+        def: defs.get(typ).clone(),
+        upgrades: Upgrades::default(),
+        delay: 0,
+        shots: 0,
+        charge: 0,
+    };
+    for (upgrade, level) in upgrades {
+        for _ in 0..level {
+            w.apply_upgrade(upgrade);
         }
-        false
-    }
-}
-
-pub fn new_weapon(typ: WeaponType) -> Box<dyn Weapon> {
-    match typ {
-        WeaponType::BackupLaser => Box::new(BackupLaser::new()),
-        WeaponType::AutoLaser => Box::new(AutoLaser::new()),
-        WeaponType::BurstLaser => Box::new(BurstLaser::new()),
-        WeaponType::DoubleLaser => Box::new(DoubleLaser::new()),
-        WeaponType::Shotgun => Box::new(Shotgun::new()),
-        WeaponType::SuperShotgun => Box::new(SuperShotgun::new()),
-        WeaponType::ReverseShotgun => Box::new(ReverseShotgun::new()),
     }
+    Box::new(w)
 }
 
 pub struct WeaponSelectorUI {
@@ -489,6 +502,7 @@ impl WeaponSelectorUI {
 }
 
 pub fn add_ammo(
+    defs: &WeaponDefs,
     weapons: &mut VecDeque<Box<dyn Weapon>>,
     ammo: &mut EnumMap<AmmoType, AmmoQuantity>,
     selector: &mut WeaponSelectorUI,
@@ -498,23 +512,24 @@ pub fn add_ammo(
     ammo[typ] = (ammo[typ] + amt).min(ammo_max(typ));
     if let Some(n) = weapons
         .iter()
-        .position(|w| w.get_type() == WeaponType::BackupLaser)
+        .position(|w| w.get_type() == WeaponType::BACKUP_LASER)
     {
         // there is a backup laser in inventory at position n
         // we should remove it if the player can now use anything else
         if weapons.iter().any(|w| {
-            ammo[w.get_ammo_type()] >= w.get_ammo_use() && w.get_type() != WeaponType::BackupLaser
+            ammo[w.get_ammo_type()] >= w.get_ammo_use() && w.get_type() != WeaponType::BACKUP_LASER
         }) {
             weapons.remove(n);
             if n == 0 {
                 // backup laser was previously the selected weapon
-                select_fireable_weapon(weapons, ammo, selector)
+                select_fireable_weapon(defs, weapons, ammo, selector)
             }
         }
     }
 }
 
 pub fn select_fireable_weapon(
+    defs: &WeaponDefs,
     weapons: &mut VecDeque<Box<dyn Weapon>>,
     ammo: &mut EnumMap<AmmoType, AmmoQuantity>,
     selector: &mut WeaponSelectorUI,
@@ -538,6 +553,6 @@ pub fn select_fireable_weapon(
         }
     }
     // if we couldn't find anything, add a backup laser to inventory
-    weapons.push_front(new_weapon(WeaponType::BackupLaser));
+    weapons.push_front(new_weapon(defs, WeaponType::BACKUP_LASER));
     selector.change(-1.0);
 }
@@ -0,0 +1,277 @@
+use crate::datafile::{self, set, set_value, Line};
+use crate::weapon::{AmmoQuantity, AmmoType};
+use macroquad::prelude::load_string;
+
+const WEAPONS_FILE: &str = "weapons.txt";
+const DEFAULT_MAX_WEAPONS: usize = 3;
+
+// an index into the weapon definitions
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct WeaponType(usize);
+
+impl WeaponType {
+    // always first in the file, so it exists whatever else has been defined
+    pub const BACKUP_LASER: WeaponType = WeaponType(0);
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum FireMode {
+    Semi,
+    Auto,
+    Burst,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ProjectileKind {
+    Laser,
+    Pellet,
+    Rocket,
+    // hits instantly along a line instead of travelling
+    Beam,
+}
+
+// what the alt fire button does, if anything
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum AltFire {
+    None,
+    // hold to charge up a bigger laser, fires on release
+    Charge,
+    // a single heavy shot that hits hard
+    Slug,
+    // lobbed, and explodes where it lands
+    Grenade,
+}
+
+#[derive(Clone)]
+pub struct WeaponDef {
+    pub id: String,
+    pub name: String,
+    pub name_indef: String,
+    pub mode: FireMode,
+    pub burst: u8,
+    pub cooldown: u8,
+    pub ammo: AmmoType,
+    pub ammo_use: AmmoQuantity,
+    pub projectile: ProjectileKind,
+    pub count: usize,
+    pub spread: f32,
+    pub speed: f32,
+    pub recoil: f32,
+    pub drag: bool,
+    pub knockback: f32,
+    pub damage: u16,
+    pub reverse: bool,
+    pub muzzle: i32,
+    pub splash_radius: f32,
+    pub splash_force: f32,
+    pub pierce: u8,
+    pub bounce: u8,
+    pub restitution: f32,
+    pub range: f32,
+    pub alt: AltFire,
+    pub alt_ammo_use: AmmoQuantity,
+    pub sprite: usize,
+    pub v_offset: f32,
+}
+
+impl WeaponDef {
+    fn new(id: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            name: id.replace('_', " "),
+            // filled in from the name once parsing is done, unless given
+            name_indef: String::new(),
+            mode: FireMode::Semi,
+            burst: 1,
+            cooldown: 0,
+            ammo: AmmoType::Cell,
+            ammo_use: 1,
+            projectile: ProjectileKind::Laser,
+            count: 1,
+            spread: 0.0,
+            speed: 10.0,
+            recoil: 0.0,
+            drag: false,
+            knockback: 0.0,
+            damage: 1,
+            reverse: false,
+            muzzle: 9,
+            splash_radius: 0.0,
+            splash_force: 0.0,
+            pierce: 0,
+            bounce: 0,
+            restitution: 1.0,
+            range: 320.0,
+            alt: AltFire::None,
+            alt_ammo_use: 0,
+            sprite: 0,
+            v_offset: 0.0,
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> bool {
+        match key {
+            "name" => {
+                self.name = value.to_owned();
+                true
+            }
+            "name_indef" => {
+                self.name_indef = value.to_owned();
+                true
+            }
+            "mode" => match value {
+                "semi" => set_value(&mut self.mode, FireMode::Semi),
+                "auto" => set_value(&mut self.mode, FireMode::Auto),
+                "burst" => set_value(&mut self.mode, FireMode::Burst),
+                _ => false,
+            },
+            "ammo" => match value {
+                "cell" => set_value(&mut self.ammo, AmmoType::Cell),
+                "shell" => set_value(&mut self.ammo, AmmoType::Shell),
+                "rocket" => set_value(&mut self.ammo, AmmoType::Rocket),
+                _ => false,
+            },
+            "projectile" => match value {
+                "laser" => set_value(&mut self.projectile, ProjectileKind::Laser),
+                "pellet" => set_value(&mut self.projectile, ProjectileKind::Pellet),
+                "rocket" => set_value(&mut self.projectile, ProjectileKind::Rocket),
+                "beam" => set_value(&mut self.projectile, ProjectileKind::Beam),
+                _ => false,
+            },
+            "alt" => match value {
+                "none" => set_value(&mut self.alt, AltFire::None),
+                "charge" => set_value(&mut self.alt, AltFire::Charge),
+                "slug" => set_value(&mut self.alt, AltFire::Slug),
+                "grenade" => set_value(&mut self.alt, AltFire::Grenade),
+                _ => false,
+            },
+            "alt_ammo_use" => set(&mut self.alt_ammo_use, value),
+            "burst" => set(&mut self.burst, value),
+            "cooldown" => set(&mut self.cooldown, value),
+            "ammo_use" => set(&mut self.ammo_use, value),
+            "count" => set(&mut self.count, value),
+            "spread" => set(&mut self.spread, value),
+            "speed" => set(&mut self.speed, value),
+            "recoil" => set(&mut self.recoil, value),
+            "drag" => set(&mut self.drag, value),
+            "knockback" => set(&mut self.knockback, value),
+            "damage" => set(&mut self.damage, value),
+            "reverse" => set(&mut self.reverse, value),
+            "muzzle" => set(&mut self.muzzle, value),
+            "splash_radius" => set(&mut self.splash_radius, value),
+            "splash_force" => set(&mut self.splash_force, value),
+            "pierce" => set(&mut self.pierce, value),
+            "bounce" => set(&mut self.bounce, value),
+            "restitution" => set(&mut self.restitution, value),
+            "range" => set(&mut self.range, value),
+            "sprite" => set(&mut self.sprite, value),
+            "v_offset" => set(&mut self.v_offset, value),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct WeaponDefs {
+    defs: Vec<WeaponDef>,
+    // how many weapons can be carried at once, not counting the backup laser
+    pub max_weapons: usize,
+}
+
+impl WeaponDefs {
+    // an [id] line starts each weapon, followed by "name value" pairs
+    // the only setting allowed before the first [id] is max_weapons
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut defs: Vec<WeaponDef> = Vec::new();
+        let mut max_weapons = DEFAULT_MAX_WEAPONS;
+        for (line, parsed) in datafile::lines(text) {
+            let (key, value) = match parsed {
+                Line::Section(id) => {
+                    defs.push(WeaponDef::new(id));
+                    continue;
+                }
+                Line::Setting(key, value) => (key, value),
+            };
+            let def = match defs.last_mut() {
+                Some(def) => def,
+                None => {
+                    let ok = match key {
+                        // there has to be room for at least the weapon being picked up
+                        "max_weapons" => match value.parse() {
+                            Ok(n) if n > 0 => set_value(&mut max_weapons, n),
+                            _ => false,
+                        },
+                        _ => false,
+                    };
+                    if !ok {
+                        println!("{}: bad setting '{}'", WEAPONS_FILE, line);
+                    }
+                    continue;
+                }
+            };
+            if !def.set(key, value) {
+                println!("{}: bad setting '{}' for {}", WEAPONS_FILE, line, def.id);
+            }
+        }
+        for def in &mut defs {
+            if def.name_indef.is_empty() {
+                def.name_indef = format!("a {}", def.name);
+            }
+        }
+        match defs.first() {
+            Some(def) if def.id == "backup_laser" => Ok(Self { defs, max_weapons }),
+            _ => Err(format!(
+                "{}: the first weapon must be backup_laser",
+                WEAPONS_FILE
+            )),
+        }
+    }
+
+    pub fn find(&self, id: &str) -> Option<WeaponType> {
+        self.defs.iter().position(|d| d.id == id).map(WeaponType)
+    }
+
+    pub fn get(&self, typ: WeaponType) -> &WeaponDef {
+        &self.defs[typ.0]
+    }
+}
+
+pub async fn load_weapon_defs() -> Result<WeaponDefs, String> {
+    let text = load_string(WEAPONS_FILE)
+        .await
+        .map_err(|_| format!("couldn't load {}", WEAPONS_FILE))?;
+    WeaponDefs::parse(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHIPPED: &str = include_str!("../assets/weapons.txt");
+
+    #[test]
+    fn shipped_weapons_load() {
+        let defs = WeaponDefs::parse(SHIPPED).unwrap();
+        assert_eq!(defs.max_weapons, 3);
+        let backup = defs.get(WeaponType::BACKUP_LASER);
+        assert_eq!(backup.id, "backup_laser");
+        assert_eq!(backup.ammo_use, 0);
+        assert_eq!(backup.name_indef, "a backup laser");
+        let auto = defs.get(defs.find("auto_laser").unwrap());
+        assert_eq!(auto.name_indef, "an auto-laser");
+    }
+
+    #[test]
+    fn backup_laser_comes_first() {
+        assert!(WeaponDefs::parse("[shotgun]\n[backup_laser]\n").is_err());
+        assert!(WeaponDefs::parse("").is_err());
+    }
+
+    #[test]
+    fn only_max_weapons_before_the_first_weapon() {
+        let defs = WeaponDefs::parse("max_weapons 5\nspeed 3\n[backup_laser]\n").unwrap();
+        assert_eq!(defs.max_weapons, 5);
+        let defs = WeaponDefs::parse("max_weapons 0\n[backup_laser]\n").unwrap();
+        assert_eq!(defs.max_weapons, DEFAULT_MAX_WEAPONS);
+    }
+}